@@ -0,0 +1,81 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io;
+
+use compiler::{generator::Generator, lexer::Lexer, parser::Parser};
+use virtual_machine::{output::SharedBuffer, vm::Machine};
+
+mod synthetic;
+
+fn build(source: &str) -> Vec<u8> {
+    let tokens = Lexer::lex(source).unwrap();
+    Generator::generate(Parser::parse(tokens).unwrap()).unwrap()
+}
+
+fn run(program: Vec<u8>) -> u64 {
+    let mut machine = Machine::new();
+    machine.set_output(Box::new(io::sink()));
+    machine.run(program, false)
+}
+
+fn arithmetic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm/arithmetic_loop");
+
+    for iterations in [1_000, 10_000, 100_000].iter() {
+        let program = build(&synthetic::arithmetic_loop(*iterations));
+        assert_eq!((1..=*iterations).rev().fold(0, |total, i| (total + i * 3) % 1_000_003), run(program.clone()));
+
+        group.throughput(Throughput::Elements(*iterations));
+        group.bench_with_input(BenchmarkId::from_parameter(iterations), &program, |b, program| b.iter(|| run(program.clone())));
+    }
+
+    group.finish();
+}
+
+fn recursion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm/recursion");
+
+    for depth in [100, 1_000, 10_000].iter() {
+        let program = build(&synthetic::recursion(*depth));
+        assert_eq!(0, run(program.clone()));
+
+
+        group.throughput(Throughput::Elements(*depth));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &program, |b, program| b.iter(|| run(program.clone())));
+    }
+
+    group.finish();
+}
+
+fn printing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm/printstr");
+
+    for lines in [100, 1_000, 10_000].iter() {
+        let program = build(&synthetic::print_loop("Hello, world!", *lines));
+
+        let output = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(output.clone()));
+        machine.run(program.clone(), false);
+        assert_eq!(vec!["Hello, world!"; *lines as usize], output.to_string_lossy().lines().collect::<Vec<&str>>());
+
+        group.throughput(Throughput::Elements(*lines));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &program, |b, program| b.iter(|| run(program.clone())));
+    }
+
+    group.finish();
+}
+
+fn compiling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compiler/compile");
+
+    for functions in [10, 100, 1_000].iter() {
+        let source = synthetic::many_functions(*functions);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(functions), &source, |b, source| b.iter(|| build(source)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, arithmetic, recursion, printing, compiling);
+criterion_main!(benches);
@@ -0,0 +1,100 @@
+//Generates Nariva programs of any size for the benchmarks
+
+//loops the given number of times, updating a running total with multiplication, addition and modulo
+pub fn arithmetic_loop(iterations: u64) -> String {
+    format!("
+PUSH {}
+STORE 0
+PUSH 0
+STORE 1
+LOAD 1
+LOAD 0
+PUSH 3
+MULU
+ADDU
+PUSH 1000003
+MODU
+STORE 1
+LOAD 0
+PUSH 1
+SUBU
+DUPLI
+STORE 0
+PUSH 0
+CMP 0
+JNE -15
+LOAD 1
+", iterations)
+}
+
+//a function which calls itself until register 0 counts down to zero, so the call stack grows to the given depth
+pub fn recursion(depth: u64) -> String {
+    format!("
+PUSH {}
+STORE 0
+CALL recurse
+LOAD 0
+HALT
+
+recurse:
+    LOAD 0
+    PUSH 0
+    CMP 0
+    JNE 2
+RETURN
+    LOAD 0
+    PUSH 1
+    SUBU
+    STORE 0
+    CALL recurse
+RETURN
+", depth)
+}
+
+//prints the given text the given number of times using PRINTSTR
+pub fn print_loop(text: &str, lines: u64) -> String {
+    let pushes: String = text.chars().map(|c| format!("PUSH {}\n", c as u64)).collect();
+
+    format!("
+PUSH {lines}
+STORE 0
+{pushes}PRINTSTR {len}
+LOAD 0
+PUSH 1
+SUBU
+DUPLI
+STORE 0
+PUSH 0
+CMP 0
+JNE -{jump}
+", lines = lines, pushes = pushes, len = text.len(), jump = text.len() + 8)
+}
+
+//a program with the given number of small functions, each called once from the start of the program
+pub fn many_functions(functions: u64) -> String {
+    let mut source = String::new();
+
+    for i in 0..functions {
+        source.push_str(&format!("CALL f{}\n", i));
+    }
+    source.push_str("HALT\n");
+
+    for i in 0..functions {
+        source.push_str(&format!("
+f{}:
+    PUSH {}
+    PUSH 7
+    MULU
+    PUSH 3
+    MODU
+    PUSH 0
+    CMP 0
+    JNE 2
+    PUSH 1
+    POP
+RETURN
+", i, i));
+    }
+
+    source
+}
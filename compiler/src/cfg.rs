@@ -0,0 +1,152 @@
+use std::ops::Range;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompWarning, ir::InstructionList};
+
+//which instructions can lead to which, following jumps, calls, returns and halts
+pub struct ControlFlowGraph {
+    //instructions that can run straight after each instruction, a CALL leading to both the function and the instruction after it
+    pub successors: Vec<Vec<usize>>,
+
+    //whether each instruction can be reached when the program starts from its first instruction
+    pub reachable: Vec<bool>
+}
+
+impl ControlFlowGraph {
+    pub fn new(list: &InstructionList) -> ControlFlowGraph {
+        let len = list.instructions.len();
+
+        let successors: Vec<Vec<usize>> = list.instructions.iter().enumerate().map(|(index, instruction)| {
+            let next = index + 1;
+            let mut successors = match instruction.opcode {
+                OpCode::Halt | OpCode::Trap | OpCode::Return | OpCode::Illegal => Vec::new(),
+                OpCode::JMP => instruction.target.into_iter().collect(),
+                OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL | OpCode::Call => instruction.target.into_iter().chain(Some(next)).collect(),
+                _ => vec![next]
+            };
+
+            //running off the end of the program finishes it
+            successors.retain(|successor| *successor < len);
+            successors.dedup();
+            successors
+        }).collect();
+
+        let mut reachable = vec![false; len];
+        let mut pending = if len > 0 { vec![0] } else { Vec::new() };
+
+        while let Some(index) = pending.pop() {
+            if reachable[index] {
+                continue
+            }
+            reachable[index] = true;
+
+            pending.extend(successors[index].iter().filter(|successor| !reachable[**successor]));
+        }
+
+        ControlFlowGraph { successors, reachable }
+    }
+
+    /*
+    Warns about functions that are never called and about any other instructions which can never run.
+    Instructions are grouped into runs of consecutive unreachable lines, and instructions without a line (from included files) are not warned about.
+    */
+    pub fn warnings(&self, list: &InstructionList) -> Vec<CompWarning> {
+        let mut warnings = Vec::new();
+        let mut index = 0;
+
+        while index < list.instructions.len() {
+            if self.reachable[index] {
+                index += 1;
+                continue
+            }
+
+            let start = index;
+            let labels = &list.instructions[start].labels;
+            while index < list.instructions.len() && !self.reachable[index] && (index == start || list.instructions[index].labels.is_empty()) {
+                index += 1;
+            }
+
+            let (first_line, last_line) = (list.instructions[start].line, list.instructions[index - 1].line);
+            if first_line == 0 {
+                continue
+            }
+
+            let lines = if first_line == last_line {
+                format!("line {}", first_line)
+            }
+            else {
+                format!("lines {}-{}", first_line, last_line)
+            };
+
+            if labels.is_empty() {
+                warnings.push(CompWarning::UnreachableCode(format!("Instructions on {} can never run", lines)));
+            }
+            else {
+                for label in labels {
+                    warnings.push(CompWarning::UnusedFunction(format!("Function '{}' on {} is never called", label, lines)));
+                }
+            }
+        }
+
+        warnings
+    }
+
+    //Removes every unreachable instruction along with the labels of functions that are never called
+    pub fn strip(&self, list: &mut InstructionList) -> bool {
+        let removed: Vec<bool> = self.reachable.iter().map(|reachable| !reachable).collect();
+        if !removed.contains(&true) {
+            return false
+        }
+
+        for (instruction, is_removed) in list.instructions.iter_mut().zip(removed.iter()) {
+            if *is_removed {
+                instruction.labels.clear();
+            }
+        }
+
+        list.remove(&removed);
+        true
+    }
+
+    /*
+    Splits the program into runs of instructions that always run one after the other.
+    A block starts at the program's first instruction, at functions, at anything a jump lands on and after any jump, RETURN or HALT.
+    */
+    pub fn basic_blocks(list: &InstructionList) -> Vec<Range<usize>> {
+        let len = list.instructions.len();
+        let mut starts = vec![false; len + 1];
+        if len > 0 {
+            starts[0] = true;
+        }
+
+        for (index, instruction) in list.instructions.iter().enumerate() {
+            if !instruction.labels.is_empty() {
+                starts[index] = true;
+            }
+
+            match instruction.opcode {
+                OpCode::JMP | OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL | OpCode::Return | OpCode::Halt | OpCode::Trap | OpCode::Illegal => {
+                    starts[index + 1] = true;
+                    if let Some(target) = instruction.target {
+                        starts[target] = true;
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        starts[len] = true;
+
+        let mut blocks = Vec::new();
+        let mut start = 0;
+        for (index, is_start) in starts.iter().enumerate().skip(1) {
+            if *is_start {
+                blocks.push(start..index);
+                start = index;
+            }
+        }
+
+        blocks
+    }
+}
@@ -1,46 +1,207 @@
-use crate::{error::CompError, lexer::Lexer, parser::Parser, generator::Generator};
+use crate::{error::{CompError, CompWarning}, include::{Includer, Source}, parser::Parser, generator::Generator, optimizer::Optimizer, ir::InstructionList, cfg::ControlFlowGraph, listing::Listing, program::Program, regalloc::{Allocation, RegisterAllocator}, structured, token::Token};
 use std::fs;
-use std::io::Write;
+use std::path::Path;
 
-//overarching structure that converts human readable text to machine readable code
+/*
+Overarching structure that converts human readable text to machine readable code.
+The build functions compile into a Program in memory, while the compile functions also write it to the output path.
+*/
 pub struct Compiler {
     file_path: String,
-    output: Vec<u8>
+
+    //the last program compiled
+    program: Program,
+
+    //whether the optimizer runs between parsing and generating, as with -O
+    optimize: bool,
+
+    //whether programs are compiled with a listing, which is written next to the output as a .lst file
+    listing: bool,
+
+    //text of the program being compiled, which the listing shows alongside each instruction
+    source: String,
+
+    //instructions the last program was generated from, with their labels and lines, for tools that show the code which actually runs
+    instructions: InstructionList
+}
+
+//a compiler with no output path, for building programs in memory
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new(String::new())
+    }
 }
 
 impl Compiler {
     pub fn new (file_path: String ) -> Compiler {
-        Compiler { file_path, output: Vec::new()}
+        Compiler { file_path, program: Program::default(), optimize: false, listing: false, source: String::new(), instructions: InstructionList::default() }
     }
 
-    //function that compiles a Nariva program into binary.
+    //function that compiles a Nariva program into binary, with any included files found relative to the current directory.
     pub fn compile<S: Into<String>>(&mut self, input: S, show: bool) -> Result<(), CompError> {
+        self.build(input, show)?;
+        self.write()
+    }
+
+    //compiles a .nar file, with any included files found relative to it, or a structured .nrs file
+    pub fn compile_file<P: AsRef<Path>>(&mut self, path: P, show: bool) -> Result<(), CompError> {
+        self.build_file(path, show)?;
+        self.write()
+    }
+
+    //compiles a program written in the structured language
+    pub fn compile_structured<S: Into<String>>(&mut self, input: S, show: bool) -> Result<(), CompError> {
+        self.build_structured(input, show)?;
+        self.write()
+    }
+
+    //writes the last program compiled to the output path
+    pub fn write(&self) -> Result<(), CompError> {
+        self.program.write(&self.file_path)
+    }
+
+    //same as compile but only in memory, so no files are touched unless the program includes them
+    pub fn build<S: Into<String>>(&mut self, input: S, show: bool) -> Result<&Program, CompError> {
+        self.build_relative(input, Path::new("."), show)
+    }
+
+    //same as build, with included files found relative to dir
+    pub fn build_relative<S: Into<String>>(&mut self, input: S, dir: &Path, show: bool) -> Result<&Program, CompError> {
+        self.source = input.into();
+        let source = Includer::lex(self.source.clone(), dir)?;
+        self.build_source(source, show)
+    }
+
+    pub fn build_file<P: AsRef<Path>>(&mut self, path: P, show: bool) -> Result<&Program, CompError> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path).map_err(|e| CompError::Io(format!("Cannot read '{}': {}", path.display(), e)))?;
+        if path.extension().is_some_and(|extension| extension == "nrs") {
+            self.build_structured(input, show)?;
+            self.program.files = vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())];
+            return Ok(&self.program)
+        }
+
+        let source = Includer::lex_path(path)?;
+        self.source = input;
+        self.build_source(source, show)
+    }
+
+    pub fn build_structured<S: Into<String>>(&mut self, input: S, show: bool) -> Result<&Program, CompError> {
+        self.source = input.into();
+        let instructions = structured::compile_to_instructions(&self.source)?;
+        if show {
+            println!("IR: {:?}\n", instructions.instructions);
+        }
+
+        let (parsed_tokens, lines) = instructions.to_tokens();
+        self.build_parsed(parsed_tokens, lines, Allocation::default(), show)
+    }
+
+    fn build_source(&mut self, mut source: Source, show: bool) -> Result<&Program, CompError> {
         //These represent separated "chunks" of data from the program.
-        let unparsed_tokens = Lexer::lex(input).unwrap();
+        let unparsed_tokens = std::mem::take(&mut source.tokens);
         if show {
             println!("UT: {:?}\n", unparsed_tokens);
         }
 
-        //checks to make sure that tokens obey certain grammatical rules
-        let parsed_tokens = Parser::parse(unparsed_tokens).unwrap();
+        //variables are replaced by registers, keeping the same number of tokens, with each file's variables kept apart
+        let (unparsed_tokens, allocation) = RegisterAllocator::allocate_files(unparsed_tokens, &source.files, |file, name| match source.paths[file].file_name() {
+            Some(file_name) if file != 0 => format!("{} in '{}'", name, file_name.to_string_lossy()),
+            _ => name.to_string()
+        })?;
+
+        //lines are only kept for the first file, so warnings and the line table don't mix up lines from different files
+        let lines: Vec<usize> = source.lines.iter().zip(source.files.iter())
+            .map(|(line, file)| if *file == 0 { *line } else { 0 })
+            .collect();
+
+        //checks to make sure that tokens obey certain grammatical rules, with errors naming the file and line they came from
+        let parsed_tokens = Parser::parse_located(unparsed_tokens).map_err(|(index, e)| e.context(&source.locate(index)))?;
+        self.build_parsed(parsed_tokens, lines, allocation, show)?;
+
+        //programs that aren't saved in a file still list the files they include
+        self.program.files = source.paths.into_iter().filter(|path| path.as_os_str() != "<input>").collect();
+        Ok(&self.program)
+    }
+
+    fn build_parsed(&mut self, mut parsed_tokens: Vec<Token>, mut lines: Vec<usize>, allocation: Allocation, show: bool) -> Result<&Program, CompError> {
         if show {
             println!("\nPT: {:?}", parsed_tokens);
         }
 
+        //finds code that can never run
+        let mut instructions = InstructionList::from_tokens(&parsed_tokens, &lines)?;
+        let warnings = ControlFlowGraph::new(&instructions).warnings(&instructions);
+
+        //removes instructions which have no effect
+        if self.optimize {
+            let (optimized_tokens, optimized_lines) = Optimizer::optimize(parsed_tokens, lines)?;
+            parsed_tokens = optimized_tokens;
+            lines = optimized_lines;
+            instructions = InstructionList::from_tokens(&parsed_tokens, &lines)?;
+            if show {
+                println!("\nOT: {:?}", parsed_tokens);
+            }
+        }
+
+        let line_table = Generator::line_table(&parsed_tokens, &lines).into_iter().filter(|(_, line)| *line != 0).collect();
+        let symbols = parsed_tokens.iter().zip(Generator::addresses(&parsed_tokens)).filter_map(|(token, address)| match token {
+            Token::Func(name) => Some((name.clone(), address)),
+            _ => None
+        }).collect();
+
         //converts parsed tokens into binary data
-        self.output = Generator::generate(parsed_tokens).unwrap();
+        let bytes = Generator::generate(parsed_tokens.clone())?;
         if show {
-            println!("\nBin: {:?}\n", self.output);
+            println!("\nBin: {:?}\n", bytes);
         }
 
-        //writes data to file
-        let mut file = fs::File::create(&self.file_path).unwrap();
+        let listing = if self.listing {
+            Some(Listing::generate(&parsed_tokens, &lines, &bytes, &self.source, &allocation)?)
+        }
+        else {
+            None
+        };
+
+        self.program = Program { bytes, symbols, line_table, allocation, warnings, listing, files: Vec::new() };
+        self.instructions = instructions;
+        Ok(&self.program)
+    }
+
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize
+    }
+
+    //compiles programs with a listing, which is written next to the output file
+    pub fn set_listing(&mut self, listing: bool) {
+        self.listing = listing
+    }
 
-        file.write_all(&self.output).unwrap();
-        Ok(())
+    pub fn get_program(&self) -> &Program {
+        &self.program
     }
 
     pub fn get_output(&self) -> &Vec<u8> {
-        &self.output
+        &self.program.bytes
+    }
+
+    pub fn get_warnings(&self) -> &Vec<CompWarning> {
+        &self.program.warnings
+    }
+
+    pub fn get_line_table(&self) -> &Vec<(usize, usize)> {
+        &self.program.line_table
     }
-}
\ No newline at end of file
+
+    pub fn get_allocation(&self) -> &Allocation {
+        &self.program.allocation
+    }
+
+    pub fn get_instructions(&self) -> &InstructionList {
+        &self.instructions
+    }
+
+    pub fn get_listing(&self) -> &str {
+        self.program.listing.as_deref().unwrap_or("")
+    }
+}
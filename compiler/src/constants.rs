@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::{error::CompError, token::Token};
+
+//a number while a constant's expression is being worked out, whole numbers being kept wide enough that either a NumU or NumI can be produced
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Int(i128),
+    Float(f64)
+}
+
+//works out the value of expressions after .const NAME =
+pub struct ConstEvaluator<'a> {
+    chars: Vec<char>,
+    index: usize,
+    constants: &'a HashMap<String, Token>
+}
+
+impl<'a> ConstEvaluator<'a> {
+    /*
+    Evaluates +, -, *, / and % on whole numbers, decimals, characters such as 'A' and earlier constants, with brackets.
+    Whole numbers give a NumU if the result isn't negative and a NumI otherwise, and anything involving a decimal gives a NumF, the same as numbers written in a program.
+    */
+    pub fn evaluate(expression: &str, constants: &'a HashMap<String, Token>) -> Result<Token, CompError> {
+        let mut evaluator = ConstEvaluator { chars: expression.chars().collect(), index: 0, constants };
+
+        let value = evaluator.sum()?;
+        evaluator.skip_spaces();
+        if let Some(c) = evaluator.peek() {
+            return Err(CompError::UnexpectedChar(format!("Unexpected '{}' in expression", c)))
+        }
+
+        match value {
+            Value::Float(num) => Ok(Token::NumF(num)),
+            Value::Int(num) if num >= 0 && num <= u64::MAX as i128 => Ok(Token::NumU(num as u64)),
+            Value::Int(num) if num >= i64::MIN as i128 && num < 0 => Ok(Token::NumI(num as i64)),
+            Value::Int(_) => Err(CompError::Overflow("Expression is too large to fit in 64 bits".into()))
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+
+    //terms joined by + and -
+    fn sum(&mut self) -> Result<Value, CompError> {
+        let mut value = self.product()?;
+
+        loop {
+            self.skip_spaces();
+            let op = match self.peek() {
+                Some(c) if c == '+' || c == '-' => c,
+                _ => return Ok(value)
+            };
+            self.index += 1;
+
+            let right = self.product()?;
+            value = ConstEvaluator::apply(op, value, right)?;
+        }
+    }
+
+    //factors joined by *, / and %
+    fn product(&mut self) -> Result<Value, CompError> {
+        let mut value = self.factor()?;
+
+        loop {
+            self.skip_spaces();
+            let op = match self.peek() {
+                Some(c) if c == '*' || c == '/' || c == '%' => c,
+                _ => return Ok(value)
+            };
+            self.index += 1;
+
+            let right = self.factor()?;
+            value = ConstEvaluator::apply(op, value, right)?;
+        }
+    }
+
+    fn factor(&mut self) -> Result<Value, CompError> {
+        self.skip_spaces();
+
+        match self.peek() {
+            Some('-') => {
+                self.index += 1;
+                ConstEvaluator::apply('-', Value::Int(0), self.factor()?)
+            },
+            Some('(') => {
+                self.index += 1;
+                let value = self.sum()?;
+                self.skip_spaces();
+                if self.peek() != Some(')') {
+                    return Err(CompError::UnexpectedChar("Missing ')' in expression".into()))
+                }
+                self.index += 1;
+                Ok(value)
+            },
+            Some('\'') => {
+                match (self.chars.get(self.index + 1), self.chars.get(self.index + 2)) {
+                    (Some(c), Some('\'')) => {
+                        self.index += 3;
+                        Ok(Value::Int(*c as i128))
+                    },
+                    _ => Err(CompError::UnexpectedChar("Characters must be written as a single character between quotes, e.g. 'A'".into()))
+                }
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.index;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.' || c == '_') {
+                    self.index += 1;
+                }
+
+                let text: String = self.chars[start..self.index].iter().filter(|c| **c != '_').collect();
+                match (text.parse::<i128>(), text.parse::<f64>()) {
+                    (Ok(num), _) => Ok(Value::Int(num)),
+                    (_, Ok(num)) => Ok(Value::Float(num)),
+                    _ => Err(CompError::UnexpectedChar(format!("'{}' is not a number", text)))
+                }
+            },
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.index;
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.index += 1;
+                }
+
+                let name: String = self.chars[start..self.index].iter().collect();
+                match self.constants.get(&name) {
+                    Some(Token::NumU(num)) => Ok(Value::Int(*num as i128)),
+                    Some(Token::NumI(num)) => Ok(Value::Int(*num as i128)),
+                    Some(Token::NumF(num)) => Ok(Value::Float(*num)),
+                    _ => Err(CompError::UnexpectedChar(format!("Constant '{}' doesn't exist", name)))
+                }
+            },
+            Some(c) => Err(CompError::UnexpectedChar(format!("Unexpected '{}' in expression", c))),
+            None => Err(CompError::UnexpectedEOF("Expression ended unexpectedly".into()))
+        }
+    }
+
+    fn apply(op: char, left: Value, right: Value) -> Result<Value, CompError> {
+        let value = match (left, right) {
+            (Value::Int(left), Value::Int(right)) => {
+                let result = match op {
+                    '+' => left.checked_add(right),
+                    '-' => left.checked_sub(right),
+                    '*' => left.checked_mul(right),
+                    '/' | '%' if right == 0 => return Err(CompError::Impossible("Division by zero in expression".into())),
+                    '/' => left.checked_div(right),
+                    _ => left.checked_rem(right)
+                };
+
+                match result {
+                    Some(num) if num >= i64::MIN as i128 && num <= u64::MAX as i128 => Value::Int(num),
+                    _ => return Err(CompError::Overflow("Expression is too large to fit in 64 bits".into()))
+                }
+            },
+            (left, right) => {
+                let [left, right] = [left, right].map(|value| match value {
+                    Value::Int(num) => num as f64,
+                    Value::Float(num) => num
+                });
+
+                Value::Float(match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    '/' => left / right,
+                    _ => left % right
+                })
+            }
+        };
+
+        Ok(value)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use virtual_machine::{coverage::Coverage, instruction::OpCode};
+
+//maps coverage recorded by the vm back onto the source lines of a program
+pub struct CoverageReport;
+
+//what ran on a single source line
+#[derive(Debug, Default, PartialEq)]
+struct LineCoverage {
+    count: u64,
+    //taken and not taken counts for each conditional jump on the line, None if the jump never ran
+    branches: Vec<Option<(u64, u64)>>
+}
+
+impl CoverageReport {
+    /*
+    Writes the coverage in the lcov tracefile format so it can be read by tools such as genhtml.
+    program is the compiled binary and line_table pairs the address of each instruction with its source line.
+    */
+    pub fn lcov(coverage: &Coverage, program: &[u8], line_table: &[(usize, usize)], source_path: &str) -> String {
+        let lines = CoverageReport::lines(coverage, program, line_table);
+        let mut output = format!("TN:\nSF:{}\n", source_path);
+
+        let mut branches_found = 0;
+        let mut branches_hit = 0;
+        for (line, line_coverage) in &lines {
+            for (block, branch) in line_coverage.branches.iter().enumerate() {
+                match branch {
+                    Some((taken, not_taken)) => {
+                        output.push_str(&format!("BRDA:{},{},0,{}\n", line, block, taken));
+                        output.push_str(&format!("BRDA:{},{},1,{}\n", line, block, not_taken));
+                        branches_hit += (*taken > 0) as usize + (*not_taken > 0) as usize;
+                    },
+                    None => {
+                        output.push_str(&format!("BRDA:{},{},0,-\n", line, block));
+                        output.push_str(&format!("BRDA:{},{},1,-\n", line, block));
+                    }
+                }
+                branches_found += 2;
+            }
+        }
+        output.push_str(&format!("BRF:{}\nBRH:{}\n", branches_found, branches_hit));
+
+        for (line, line_coverage) in &lines {
+            output.push_str(&format!("DA:{},{}\n", line, line_coverage.count));
+        }
+        let lines_hit = lines.values().filter(|l| l.count > 0).count();
+        output.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), lines_hit));
+
+        output
+    }
+
+    /*
+    Prefixes every line of the source with how many times it ran, "#####" if it never ran, or nothing if it has no instructions.
+    Conditional jumps are followed by how often they were taken and not taken.
+    */
+    pub fn annotate(coverage: &Coverage, program: &[u8], line_table: &[(usize, usize)], source: &str) -> String {
+        let lines = CoverageReport::lines(coverage, program, line_table);
+        let mut output = String::new();
+
+        for (index, text) in source.lines().enumerate() {
+            match lines.get(&(index + 1)) {
+                Some(line_coverage) => {
+                    let count = match line_coverage.count {
+                        0 => "#####".to_string(),
+                        n => n.to_string()
+                    };
+                    output.push_str(&format!("{:>9} | {}", count, text));
+
+                    for branch in &line_coverage.branches {
+                        match branch {
+                            Some((taken, not_taken)) => output.push_str(&format!("    [taken {}, not taken {}]", taken, not_taken)),
+                            None => output.push_str("    [never ran]")
+                        }
+                    }
+                },
+                None => output.push_str(&format!("{:>9} | {}", "", text))
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn lines(coverage: &Coverage, program: &[u8], line_table: &[(usize, usize)]) -> BTreeMap<usize, LineCoverage> {
+        let mut lines: BTreeMap<usize, LineCoverage> = BTreeMap::new();
+
+        for (address, line) in line_table {
+            let line_coverage = lines.entry(*line).or_default();
+            let count = coverage.count(*address);
+            line_coverage.count = line_coverage.count.max(count);
+
+            let is_branch = program.get(*address).is_some_and(|opcode| OpCode::from(*opcode).is_conditional_jump());
+            if is_branch {
+                let branch = coverage.branch(*address);
+                line_coverage.branches.push(if count > 0 { Some((branch.taken, branch.not_taken)) } else { None });
+            }
+        }
+
+        lines
+    }
+}
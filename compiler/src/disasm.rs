@@ -0,0 +1,62 @@
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompError, ir::InstructionList, lexer::Lexer, token::Token};
+
+/*
+Turns a compiled program back into .nar source, which compiles to the same binary.
+Jumps are written as the number of opcodes they move, as in source, and functions are named after their address, e.g. fn@102.
+*/
+pub struct Disassembler;
+
+impl Default for Disassembler {
+    fn default() -> Disassembler {
+        Disassembler::new()
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Disassembler {
+        Disassembler
+    }
+
+    pub fn disassemble(program: &[u8]) -> Result<String, CompError> {
+        let list = InstructionList::from_binary(program)?;
+        let mut output = String::new();
+
+        //code after the first function is indented underneath its label
+        let mut indent = "";
+
+        for (index, instruction) in list.instructions.iter().enumerate() {
+            for label in &instruction.labels {
+                output.push_str(&format!("\n{}:\n", label));
+                indent = "    ";
+            }
+
+            let word = format!("{:?}", instruction.opcode).to_uppercase();
+            let operand = match (instruction.opcode, instruction.target, &instruction.operand) {
+                (OpCode::Call, Some(target), _) => list.instructions.get(target).map_or(list.end_labels.first(), |target| target.labels.first()).cloned(),
+                (_, Some(target), _) => Some((target as i64 - index as i64).to_string()),
+                (_, None, Some(Token::NumU(num))) => Some(num.to_string()),
+                _ => None
+            };
+
+            let mut line = match operand {
+                Some(operand) => format!("{}{} {}", indent, word, operand),
+                None => format!("{}{}", indent, word)
+            };
+            if let Some(message) = &instruction.message {
+                line.push(' ');
+                line.push_str(&Lexer::quote(message));
+            }
+
+            output.push_str(&line);
+            output.push('\n');
+        }
+
+        for label in &list.end_labels {
+            output.push_str(&format!("\n{}:\n", label));
+        }
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{cfg::ControlFlowGraph, compiler::Compiler, error::CompError, ir::InstructionList, token::Token};
+
+//writes a program's control-flow graph in Graphviz's DOT language
+pub struct Dot;
+
+impl Default for Dot {
+    fn default() -> Dot {
+        Dot::new()
+    }
+}
+
+impl Dot {
+    pub fn new() -> Dot {
+        Dot
+    }
+
+    //graphs are made from what the compiler builds, so included files are followed and test blocks left out just as when compiling
+    pub fn from_source<S: Into<String>>(input: S) -> Result<String, CompError> {
+        let mut compiler = Compiler::default();
+        compiler.build(input, false)?;
+        Ok(Dot::control_flow(compiler.get_instructions()))
+    }
+
+    //same as from_source for a .nar file, with includes found relative to it, or a structured .nrs file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<String, CompError> {
+        let mut compiler = Compiler::default();
+        compiler.build_file(path, false)?;
+        Ok(Dot::control_flow(compiler.get_instructions()))
+    }
+
+    pub fn from_binary(program: &[u8]) -> Result<String, CompError> {
+        Ok(Dot::control_flow(&InstructionList::from_binary(program)?))
+    }
+
+    /*
+    Each function is drawn as a cluster of its basic blocks, with jumps between blocks labelled by when they are taken.
+    Calls are drawn as dashed edges from the calling block to the start of the function, which makes up the call graph.
+    */
+    pub fn control_flow(list: &InstructionList) -> String {
+        let blocks = ControlFlowGraph::basic_blocks(list);
+        let block_of = |index: usize| blocks.iter().position(|block| block.contains(&index));
+
+        let mut output = String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+
+        //blocks are grouped under the function whose label comes before them
+        let mut functions: Vec<(String, Vec<usize>)> = vec![("main".to_string(), Vec::new())];
+        for (block_index, block) in blocks.iter().enumerate() {
+            if let Some(label) = list.instructions[block.start].labels.first() {
+                functions.push((label.clone(), Vec::new()));
+            }
+            functions.last_mut().unwrap().1.push(block_index);
+        }
+
+        for (function_index, (name, function_blocks)) in functions.iter().enumerate() {
+            if function_blocks.is_empty() {
+                continue
+            }
+
+            output.push_str(&format!("    subgraph cluster_{} {{\n        label=\"{}\";\n", function_index, name));
+            for block_index in function_blocks {
+                let text: String = blocks[*block_index].clone()
+                    .map(|index| format!("{}\\l", Dot::describe(list, index, &block_of)))
+                    .collect();
+                output.push_str(&format!("        b{} [label=\"{}\"];\n", block_index, text));
+            }
+            output.push_str("    }\n");
+        }
+
+        for (block_index, block) in blocks.iter().enumerate() {
+            for index in block.clone() {
+                let instruction = &list.instructions[index];
+                if let (OpCode::Call, Some(target)) = (instruction.opcode, instruction.target.and_then(block_of)) {
+                    output.push_str(&format!("    b{} -> b{} [style=dashed, label=\"call\"];\n", block_index, target));
+                }
+            }
+
+            let last = &list.instructions[block.end - 1];
+            let taken = match last.opcode {
+                OpCode::JMP => Some("always"),
+                OpCode::JE => Some("equal"),
+                OpCode::JNE => Some("not equal"),
+                OpCode::JG => Some("greater"),
+                OpCode::JL => Some("less"),
+                _ => None
+            };
+
+            if let (Some(condition), Some(target)) = (taken, last.target.and_then(block_of)) {
+                output.push_str(&format!("    b{} -> b{} [label=\"{}\"];\n", block_index, target, condition));
+            }
+
+            match last.opcode {
+                OpCode::JMP | OpCode::Return | OpCode::Halt | OpCode::Trap | OpCode::Illegal => (),
+                _ if block.end < list.instructions.len() => {
+                    let label = if last.opcode.is_conditional_jump() { " [label=\"otherwise\"]" } else { "" };
+                    output.push_str(&format!("    b{} -> b{}{};\n", block_index, block_index + 1, label));
+                },
+                _ => ()
+            }
+        }
+
+        output.push_str("}\n");
+        output
+    }
+
+    //an instruction as written, with jumps naming the block they go to and calls the function
+    fn describe(list: &InstructionList, index: usize, block_of: &dyn Fn(usize) -> Option<usize>) -> String {
+        let instruction = &list.instructions[index];
+        let target_name = instruction.target.map(|target| match (instruction.opcode, list.instructions.get(target).and_then(|i| i.labels.first())) {
+            (OpCode::Call, Some(label)) => label.clone(),
+            _ => match block_of(target) {
+                Some(block) => format!("b{}", block),
+                None => "end".to_string()
+            }
+        });
+
+        match (target_name, &instruction.operand) {
+            (Some(name), _) => format!("{} {}", instruction.word, name),
+            (None, Some(Token::NumU(num))) => format!("{} {}", instruction.word, num),
+            (None, Some(Token::NumI(num))) => format!("{} {}", instruction.word, num),
+            (None, Some(Token::NumF(num))) => format!("{} {}", instruction.word, num),
+            (None, _) => instruction.word.clone()
+        }
+    }
+}
@@ -6,4 +6,32 @@ pub enum CompError {
     UnexpectedChar(String),
     Impossible(String),
     Overflow(String),
+
+    //files that could not be read or written
+    Io(String),
+
+    //a file given to the test runner that has no tests in it
+    NoTests(String),
+}
+
+impl CompError {
+    //adds where the error happened to the end of its message
+    pub fn context(self, context: &str) -> CompError {
+        match self {
+            CompError::UnexpectedEOF(e) => CompError::UnexpectedEOF(format!("{} {}", e, context)),
+            CompError::UnexpectedChar(e) => CompError::UnexpectedChar(format!("{} {}", e, context)),
+            CompError::Impossible(e) => CompError::Impossible(format!("{} {}", e, context)),
+            CompError::Overflow(e) => CompError::Overflow(format!("{} {}", e, context)),
+            CompError::Io(e) => CompError::Io(format!("{} {}", e, context)),
+            CompError::NoTests(e) => CompError::NoTests(format!("{} {}", e, context))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+
+//problems that do not stop compilation
+pub enum CompWarning {
+    UnreachableCode(String),
+    UnusedFunction(String),
 }
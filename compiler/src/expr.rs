@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompError, structured::{ast::*, scanner::SToken}, token::Token};
+
+/*
+Parses infix expressions such as (a + b) * 3 % n by precedence climbing.
+Each operator binds its operands with a strength, and an operand only joins the expression on its left when that expression's operator binds less strongly.
+*/
+pub struct ExprParser<'a> {
+    tokens: &'a [(SToken, usize)],
+    index: &'a mut usize
+}
+
+//how strongly each operator holds on to its operands
+fn binding_power(token: &SToken) -> Option<(BinaryOp, u8)> {
+    match token {
+        SToken::Symbol("+") => Some((BinaryOp::Add, 10)),
+        SToken::Symbol("-") => Some((BinaryOp::Sub, 10)),
+        SToken::Symbol("*") => Some((BinaryOp::Mul, 20)),
+        SToken::Symbol("/") => Some((BinaryOp::Div, 20)),
+        SToken::Symbol("%") => Some((BinaryOp::Mod, 20)),
+        _ => None
+    }
+}
+
+//negation binds more strongly than any operator, so -a * b is (-a) * b
+const PREFIX_POWER: u8 = 30;
+
+impl<'a> ExprParser<'a> {
+    //parses the expression starting at index, leaving index at the first token after it
+    pub fn parse(tokens: &'a [(SToken, usize)], index: &'a mut usize) -> Result<Expr, CompError> {
+        let mut parser = ExprParser { tokens, index };
+        parser.expression(0)
+    }
+
+    fn peek(&self) -> Option<&SToken> {
+        self.tokens.get(*self.index).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(*self.index).or_else(|| self.tokens.last()).map_or(1, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Result<SToken, CompError> {
+        match self.tokens.get(*self.index) {
+            Some((token, _)) => {
+                *self.index += 1;
+                Ok(token.clone())
+            },
+            None => Err(CompError::UnexpectedEOF(format!("Expression ended unexpectedly on line {}", self.line())))
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), CompError> {
+        let line = self.line();
+        match self.next()? {
+            SToken::Symbol(found) if found == symbol => Ok(()),
+            found => Err(CompError::UnexpectedChar(format!("Expected '{}' on line {} but found {}", symbol, line, found.describe())))
+        }
+    }
+
+    //parses operators which bind more strongly than min_power
+    fn expression(&mut self, min_power: u8) -> Result<Expr, CompError> {
+        let mut left = self.prefix()?;
+
+        while let Some((op, power)) = self.peek().and_then(binding_power) {
+            if power <= min_power {
+                break
+            }
+
+            let line = self.line();
+            self.next()?;
+
+            let right = self.expression(power)?;
+            left = Expr { kind: ExprKind::Binary(op, Box::new(left), Box::new(right)), line };
+        }
+
+        Ok(left)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, CompError> {
+        let line = self.line();
+
+        let kind = match self.next()? {
+            SToken::Int(num) => ExprKind::Int(num),
+            SToken::Float(num) => ExprKind::Float(num),
+            SToken::Symbol("-") => ExprKind::Neg(Box::new(self.expression(PREFIX_POWER)?)),
+            SToken::Symbol("(") => {
+                let value = self.expression(0)?;
+                self.expect(")")?;
+                return Ok(value)
+            },
+            SToken::Ident(name) => {
+                if self.peek() != Some(&SToken::Symbol("(")) {
+                    ExprKind::Var(name)
+                }
+                else {
+                    self.next()?;
+                    let mut args = Vec::new();
+                    while self.peek() != Some(&SToken::Symbol(")")) {
+                        if !args.is_empty() {
+                            self.expect(",")?;
+                        }
+                        args.push(self.expression(0)?);
+                    }
+                    self.expect(")")?;
+                    ExprKind::Call(name, args)
+                }
+            },
+            found => return Err(CompError::UnexpectedChar(format!("Expected a value on line {} but found {}", line, found.describe())))
+        };
+
+        Ok(Expr { kind, line })
+    }
+}
+
+//what a name in an expression refers to, with variables giving the operand that LOAD reads them with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Variable(Token, Type),
+    Constant(Token)
+}
+
+//where lowered expressions are written, and how names and calls are looked up
+pub trait ExprTarget {
+    fn emit(&mut self, opcode: OpCode, operand: Option<Token>, line: usize);
+    fn operand(&self, name: &str, line: usize) -> Result<Operand, CompError>;
+
+    //return type of a function, which is None for functions that don't return a value
+    fn returns(&self, name: &str, line: usize) -> Result<Option<Type>, CompError>;
+
+    //pushes the arguments, calls the function and leaves its return value on the stack
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<(), CompError>;
+}
+
+fn constant_type(token: &Token) -> Type {
+    match token {
+        Token::NumI(_) => Type::I64,
+        Token::NumF(_) => Type::F64,
+        _ => Type::U64
+    }
+}
+
+//type that two values are converted to when used together: floats win over intergers, which win over whole numbers
+pub fn combine(left: Option<Type>, right: Option<Type>) -> Option<Type> {
+    match (left, right) {
+        (Some(Type::F64), _) | (_, Some(Type::F64)) => Some(Type::F64),
+        (Some(Type::I64), _) | (_, Some(Type::I64)) => Some(Type::I64),
+        (left, right) => left.or(right)
+    }
+}
+
+/*
+Type an expression has before it is converted to where it is used.
+This is None when it is only made of whole numbers, which take on whatever type is needed.
+*/
+pub fn infer<T: ExprTarget>(target: &T, expr: &Expr) -> Result<Option<Type>, CompError> {
+    match &expr.kind {
+        ExprKind::Int(_) => Ok(None),
+        ExprKind::Float(_) => Ok(Some(Type::F64)),
+        ExprKind::Var(name) => match target.operand(name, expr.line)? {
+            Operand::Variable(_, ty) => Ok(Some(ty)),
+            Operand::Constant(token) => Ok(Some(constant_type(&token)))
+        },
+
+        //whole numbers become intergers when negated
+        ExprKind::Neg(value) => Ok(infer(target, value)?.map(|ty| if ty == Type::U64 { Type::I64 } else { ty })),
+        ExprKind::Binary(_, left, right) => Ok(combine(infer(target, left)?, infer(target, right)?)),
+        ExprKind::Call(name, _) => match target.returns(name, expr.line)? {
+            Some(ty) => Ok(Some(ty)),
+            None => Err(CompError::UnexpectedChar(format!("Function '{}' on line {} doesn't return a value", name, expr.line)))
+        }
+    }
+}
+
+//opcode which converts a value from one type to another, with whole numbers and intergers sharing their bits
+pub fn conversion(from: Type, to: Type) -> Option<OpCode> {
+    match (from, to) {
+        (Type::U64, Type::F64) => Some(OpCode::UtoF),
+        (Type::I64, Type::F64) => Some(OpCode::ItoF),
+        (Type::F64, Type::U64) => Some(OpCode::FtoU),
+        (Type::F64, Type::I64) => Some(OpCode::FtoI),
+        _ => None
+    }
+}
+
+//pushes the value of an expression converted to the given type
+pub fn lower<T: ExprTarget>(target: &mut T, expr: &Expr, ty: Type) -> Result<(), CompError> {
+    let line = expr.line;
+    let natural = infer(target, expr)?.unwrap_or(ty);
+
+    match &expr.kind {
+        ExprKind::Int(num) => {
+            let operand = match natural {
+                Type::U64 => Token::NumU(*num),
+                Type::I64 if *num <= i64::MAX as u64 => Token::NumI(*num as i64),
+                Type::I64 => return Err(CompError::Overflow(format!("{} on line {} is too large for an i64", num, line))),
+                Type::F64 => Token::NumF(*num as f64)
+            };
+            target.emit(OpCode::Push, Some(operand), line);
+        },
+
+        ExprKind::Float(num) => target.emit(OpCode::Push, Some(Token::NumF(*num)), line),
+
+        ExprKind::Var(name) => match target.operand(name, line)? {
+            Operand::Variable(operand, _) => target.emit(OpCode::Load, Some(operand), line),
+            Operand::Constant(token) => target.emit(OpCode::Push, Some(token), line)
+        },
+
+        ExprKind::Neg(value) => {
+            let operand = match (&value.kind, natural) {
+                (_, Type::U64) => return Err(CompError::UnexpectedChar(format!("u64 values on line {} cannot be negative", line))),
+                (ExprKind::Int(num), Type::I64) if *num <= 1 << 63 => Some(Token::NumI((*num as i64).wrapping_neg())),
+                (ExprKind::Int(num), Type::F64) => Some(Token::NumF(-(*num as f64))),
+                (ExprKind::Float(num), Type::F64) => Some(Token::NumF(-num)),
+                _ => None
+            };
+
+            match operand {
+                Some(operand) => target.emit(OpCode::Push, Some(operand), line),
+
+                //0 - value
+                None => {
+                    lower(target, &Expr { kind: ExprKind::Int(0), line }, natural)?;
+                    lower(target, value, natural)?;
+                    target.emit(if natural == Type::I64 { OpCode::SubI } else { OpCode::SubF }, None, line);
+                }
+            }
+        },
+
+        ExprKind::Binary(op, left, right) => {
+            lower(target, left, natural)?;
+            lower(target, right, natural)?;
+
+            let opcode = match (op, natural) {
+                (BinaryOp::Add, Type::U64) => OpCode::AddU,
+                (BinaryOp::Add, Type::I64) => OpCode::AddI,
+                (BinaryOp::Add, Type::F64) => OpCode::AddF,
+                (BinaryOp::Sub, Type::U64) => OpCode::SubU,
+                (BinaryOp::Sub, Type::I64) => OpCode::SubI,
+                (BinaryOp::Sub, Type::F64) => OpCode::SubF,
+                (BinaryOp::Mul, Type::U64) => OpCode::MulU,
+                (BinaryOp::Mul, Type::I64) => OpCode::MulI,
+                (BinaryOp::Mul, Type::F64) => OpCode::MulF,
+                (BinaryOp::Div, Type::U64) => OpCode::DivU,
+                (BinaryOp::Div, Type::I64) => OpCode::DivI,
+                (BinaryOp::Div, Type::F64) => OpCode::DivF,
+                (BinaryOp::Mod, Type::U64) => OpCode::ModU,
+                (BinaryOp::Mod, Type::I64) => OpCode::ModI,
+                (BinaryOp::Mod, Type::F64) => OpCode::ModF
+            };
+            target.emit(opcode, None, line);
+        },
+
+        ExprKind::Call(name, args) => target.call(name, args, line)?
+    }
+
+    if let Some(opcode) = conversion(natural, ty) {
+        target.emit(opcode, None, line);
+    }
+
+    Ok(())
+}
+
+//writes lowered expressions as tokens, for assignments in .nar files, with variables left as names for the register allocator
+pub struct TokenTarget<'a> {
+    pub tokens: Vec<Token>,
+    pub variables: &'a HashMap<String, Type>,
+    pub constants: &'a HashMap<String, Token>
+}
+
+impl<'a> ExprTarget for TokenTarget<'a> {
+    fn emit(&mut self, opcode: OpCode, operand: Option<Token>, _line: usize) {
+        self.tokens.push(Token::OpCode(format!("{:?}", opcode).to_uppercase()));
+        self.tokens.extend(operand);
+    }
+
+    fn operand(&self, name: &str, line: usize) -> Result<Operand, CompError> {
+        if let Some(ty) = self.variables.get(name) {
+            return Ok(Operand::Variable(Token::OpCode(name.to_string()), *ty))
+        }
+
+        match self.constants.get(name) {
+            Some(token) => Ok(Operand::Constant(token.clone())),
+            None => Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a variable or constant", name, line)))
+        }
+    }
+
+    fn returns(&self, name: &str, line: usize) -> Result<Option<Type>, CompError> {
+        Err(CompError::UnexpectedChar(format!("Function '{}' on line {} cannot be called in an expression, use CALL instead", name, line)))
+    }
+
+    fn call(&mut self, name: &str, _args: &[Expr], line: usize) -> Result<(), CompError> {
+        self.returns(name, line).map(|_| ())
+    }
+}
@@ -0,0 +1,129 @@
+use std::convert::TryFrom;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{ir::{Instruction, InstructionList}, token::Token};
+
+//evaluates arithmetic on literal numbers at compile time
+pub struct Folder;
+
+impl Default for Folder {
+    fn default() -> Folder {
+        Folder::new()
+    }
+}
+
+impl Folder {
+    pub fn new() -> Folder {
+        Folder
+    }
+
+    /*
+    Replaces PUSH a PUSH b OP, and PUSH a BITNOT, with a single PUSH of the result, returning whether anything changed.
+    The instructions after the first PUSH must not be reachable from anywhere else, as another path could reach them with different numbers on the stack.
+    */
+    pub fn fold(list: &mut InstructionList) -> bool {
+        let len = list.instructions.len();
+        let mut removed = vec![false; len];
+        let mut changed = false;
+        let mut index = 0;
+
+        while index + 1 < len {
+            let (folded, width) = match Folder::fold_at(list, index) {
+                Some(folded) => folded,
+                None => {
+                    index += 1;
+                    continue
+                }
+            };
+
+            list.instructions[index].operand = Some(folded);
+            for is_removed in removed.iter_mut().skip(index + 1).take(width - 1) {
+                *is_removed = true;
+            }
+
+            changed = true;
+            index += width;
+        }
+
+        if changed {
+            list.remove(&removed);
+        }
+
+        changed
+    }
+
+    //the folded number and how many instructions it replaces, starting at index
+    fn fold_at(list: &InstructionList, index: usize) -> Option<(Token, usize)> {
+        let instructions = &list.instructions;
+        let literal = |i: usize| -> Option<u64> {
+            match instructions.get(i) {
+                Some(Instruction { opcode: OpCode::Push, operand: Some(operand), .. }) if i == index || !list.is_entry_point(i) => Some(Folder::bits(operand)),
+                _ => None
+            }
+        };
+        let opcode_at = |i: usize| instructions.get(i).filter(|_| !list.is_entry_point(i)).map(|instruction| instruction.opcode);
+
+        let first = literal(index)?;
+
+        if opcode_at(index + 1) == Some(OpCode::BitNot) {
+            return Some((Token::NumU(!first), 2))
+        }
+
+        let second = literal(index + 1)?;
+        let opcode = opcode_at(index + 2)?;
+        let shift_left = instructions[index + 2].operand == Some(Token::NumU(0));
+
+        Folder::evaluate(opcode, shift_left, first, second).map(|folded| (folded, 3))
+    }
+
+    //numbers are stored as their raw bits, whichever type was written
+    fn bits(token: &Token) -> u64 {
+        match token {
+            Token::NumU(num) => *num,
+            Token::NumI(num) => u64::from_be_bytes(num.to_be_bytes()),
+            Token::NumF(num) => u64::from_be_bytes(num.to_be_bytes()),
+            _ => 0
+        }
+    }
+
+    /*
+    Works out what the vm would push after running opcode with num1 then num2 on the stack.
+    Nothing is returned if the vm would set Flag::Overflow or panic, so that the vm still does so at run time.
+    */
+    pub fn evaluate(opcode: OpCode, shift_left: bool, num1: u64, num2: u64) -> Option<Token> {
+        let [int1, int2] = [i64::from_be_bytes(num1.to_be_bytes()), i64::from_be_bytes(num2.to_be_bytes())];
+        let [float1, float2] = [f64::from_be_bytes(num1.to_be_bytes()), f64::from_be_bytes(num2.to_be_bytes())];
+
+        let folded = match opcode {
+            OpCode::AddU => Token::NumU(num1.checked_add(num2)?),
+            OpCode::SubU => Token::NumU(num1.checked_sub(num2)?),
+            OpCode::MulU => Token::NumU(num1.checked_mul(num2)?),
+            OpCode::DivU => Token::NumU(num1.checked_div(num2)?),
+            OpCode::ModU => Token::NumU(num1.checked_rem(num2)?),
+
+            OpCode::AddI => Token::NumI(int1.checked_add(int2)?),
+            OpCode::SubI => Token::NumI(int1.checked_sub(int2)?),
+            OpCode::MulI => Token::NumI(int1.checked_mul(int2)?),
+            OpCode::DivI => Token::NumI(int1.checked_div(int2)?),
+            OpCode::ModI => Token::NumI(int1.checked_rem(int2)?),
+
+            OpCode::AddF => Token::NumF(float2 + float1),
+            OpCode::SubF => Token::NumF(float1 - float2),
+            OpCode::MulF => Token::NumF(float1 * float2),
+            OpCode::DivF => Token::NumF(float1 / float2),
+            OpCode::ModF => Token::NumF(float1 % float2),
+
+            OpCode::Shift if shift_left => Token::NumU(num1.checked_shl(u32::try_from(num2).ok()?)?),
+            OpCode::Shift => Token::NumU(num1.checked_shr(u32::try_from(num2).ok()?)?),
+
+            OpCode::BitAnd => Token::NumU(num1 & num2),
+            OpCode::BitOr => Token::NumU(num1 | num2),
+            OpCode::BitXor => Token::NumU(num1 ^ num2),
+
+            _ => return None
+        };
+
+        Some(folded)
+    }
+}
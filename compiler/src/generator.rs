@@ -1,13 +1,17 @@
 extern crate virtual_machine;
 
-use std::{fmt::Error, process::Output};
-
 use crate::{error::CompError, token::Token};
-use virtual_machine::{instruction::OpCode, vm::HEADER};
+use virtual_machine::{data::DataSection, instruction::OpCode, vm::HEADER};
 
 //converts tokens into code
 pub struct Generator;
 
+impl Default for Generator {
+    fn default() -> Generator {
+        Generator::new()
+    }
+}
+
 impl Generator {
     pub fn new() -> Generator {
         Generator 
@@ -19,6 +23,10 @@ impl Generator {
         }
 
         let mut output = HEADER.to_vec();
+        let mut data = DataSection::new();
+
+        //address of the last opcode, which a message belongs to
+        let mut opcode_address = HEADER.len();
 
         let mut index = 0;
 
@@ -28,9 +36,11 @@ impl Generator {
             }
             match &input[index] {
                 Token::OpCode(word) => {
+                    opcode_address = output.len();
                     output.push(OpCode::from(word).into())
                 },
-                Token::Func(f) => unimplemented!(),
+                //labels only mark positions for the parser
+                Token::Func(_) => (),
                 Token::NumU(num) => {
                     let bytes = num.to_be_bytes();
                     output.extend_from_slice(&bytes)
@@ -43,11 +53,36 @@ impl Generator {
                     let bytes = num.to_be_bytes();
                     output.extend_from_slice(&bytes)
                 },
+                Token::Text(text) => data.messages.push((opcode_address, text.clone())),
             };
 
             index += 1
         }
 
+        output.extend_from_slice(&data.to_bytes());
         Ok(output)
     }
+
+    //Absolute address in the generated binary of every token, with labels sharing the address of what follows them
+    pub fn addresses(input: &[Token]) -> Vec<usize> {
+        let mut address = HEADER.len();
+
+        input.iter().map(|token| {
+            let start = address;
+            address += match token {
+                Token::OpCode(_) => 1,
+                Token::Func(_) | Token::Text(_) => 0,
+                Token::NumU(_) | Token::NumI(_) | Token::NumF(_) => 8
+            };
+            start
+        }).collect()
+    }
+
+    //Pairs the address of every opcode with the source line it came from, lines being given per token
+    pub fn line_table(input: &[Token], lines: &[usize]) -> Vec<(usize, usize)> {
+        Generator::addresses(input).into_iter().zip(input.iter().zip(lines.iter()))
+            .filter(|(_, (token, _))| matches!(token, Token::OpCode(_)))
+            .map(|(address, (_, line))| (address, *line))
+            .collect()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{error::CompError, lexer::Lexer, testing::TestSuite, token::Token};
+
+/*
+Lexes a program along with every file it includes with .include "path.nar".
+Each file is lexed on its own, so constants and macros belong to the file they are written in, while functions can be called from any file.
+Included files are placed after the file that includes them, so a program still starts running from the top of the first file.
+A HALT is placed between the first file and the code included into it, so a program that doesn't end with HALT doesn't run on into an included function.
+*/
+pub struct Includer {
+    //every file lexed so far, the first being the program itself
+    paths: Vec<PathBuf>,
+
+    //files which are part way through being included, used to find files that include each other
+    stack: Vec<PathBuf>,
+
+    tokens: Vec<Token>,
+    lines: Vec<usize>,
+
+    //index in paths of the file each token came from
+    files: Vec<usize>
+}
+
+//tokens of a program and all of its included files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub tokens: Vec<Token>,
+
+    //line each token was written on, within its own file
+    pub lines: Vec<usize>,
+
+    //index in paths of the file each token came from
+    pub files: Vec<usize>,
+    pub paths: Vec<PathBuf>
+}
+
+impl Source {
+    //where the token at index was written, for adding to the errors it causes
+    pub fn locate(&self, index: usize) -> String {
+        let (line, file) = match (self.lines.get(index), self.files.get(index)) {
+            (Some(line), Some(file)) => (*line, *file),
+            _ => return "at the end of the program".to_string()
+        };
+
+        let path = &self.paths[file];
+        match path.file_name() {
+            Some(name) if path.as_os_str() != "<input>" => format!("on line {} of '{}'", line, name.to_string_lossy()),
+            _ => format!("on line {}", line)
+        }
+    }
+}
+
+impl Includer {
+    //lexes a program that isn't saved in a file, with includes relative to dir
+    pub fn lex<S: Into<String>>(input: S, dir: &Path) -> Result<Source, CompError> {
+        let mut includer = Includer { paths: Vec::new(), stack: Vec::new(), tokens: Vec::new(), lines: Vec::new(), files: Vec::new() };
+        includer.lex_file(input.into(), PathBuf::from("<input>"), dir)?;
+
+        Ok(includer.finish())
+    }
+
+    pub fn lex_path<P: AsRef<Path>>(path: P) -> Result<Source, CompError> {
+        let path = path.as_ref();
+        let mut includer = Includer { paths: Vec::new(), stack: Vec::new(), tokens: Vec::new(), lines: Vec::new(), files: Vec::new() };
+        let (path, input) = Includer::read(path).map_err(|e| CompError::Io(format!("Cannot read '{}': {}", path.display(), e)))?;
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        includer.lex_file(input, path, &dir)?;
+
+        Ok(includer.finish())
+    }
+
+    fn finish(self) -> Source {
+        Source { tokens: self.tokens, lines: self.lines, files: self.files, paths: self.paths }
+    }
+
+    fn read(path: &Path) -> Result<(PathBuf, String), String> {
+        let path = fs::canonicalize(path).map_err(|e| e.to_string())?;
+        let input = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        Ok((path, input))
+    }
+
+    fn lex_file(&mut self, input: String, path: PathBuf, dir: &Path) -> Result<(), CompError> {
+        let file = self.paths.len();
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.paths.push(path.clone());
+        self.stack.push(path);
+
+        //.include lines and test blocks are blanked out, so the lexer still gives the right line numbers
+        let input = TestSuite::strip(&input);
+        let mut includes = Vec::new();
+        let input: Vec<&str> = input.lines().enumerate().map(|(index, line)| {
+            let line_text = line.trim();
+            let (word, include) = line_text.split_once(char::is_whitespace).unwrap_or((line_text, ""));
+            if word == ".include" {
+                includes.push((index + 1, include.trim().trim_matches('"').to_string()));
+                ""
+            }
+            else {
+                line
+            }
+        }).collect();
+
+        let input = input.join("\n");
+        if !input.trim().is_empty() {
+            let (tokens, lines) = Lexer::lex_with_lines(input).map_err(|e| e.context(&format!("in '{}'", name)))?;
+            self.files.extend(std::iter::repeat_n(file, tokens.len()));
+            self.tokens.extend(tokens);
+            self.lines.extend(lines);
+        }
+
+        //the program's own code stops before the code of the files it includes, which has no line of its own
+        let ends_with_halt = matches!(self.tokens.last(), Some(Token::OpCode(word)) if word.eq_ignore_ascii_case("halt"));
+        if file == 0 && !includes.is_empty() && !self.tokens.is_empty() && !ends_with_halt {
+            self.tokens.push(Token::OpCode("HALT".into()));
+            self.lines.push(0);
+            self.files.push(file);
+        }
+
+        for (line, include) in includes {
+            let context = || format!("on line {} of '{}'", line, name);
+            if include.is_empty() {
+                return Err(CompError::UnexpectedChar(format!("File needed after '.include' {}", context())))
+            }
+
+            let (path, input) = Includer::read(&dir.join(&include))
+                .map_err(|e| CompError::Io(format!("Cannot include '{}' {}: {}", include, context(), e)))?;
+
+            if let Some(start) = self.stack.iter().position(|included| *included == path) {
+                let cycle: Vec<String> = self.stack[start..].iter().chain(Some(&path)).map(|path| path.display().to_string()).collect();
+                return Err(CompError::Impossible(format!("Files include each other {}: {}", context(), cycle.join(" -> "))))
+            }
+
+            //every file is only included once
+            if self.paths.contains(&path) {
+                continue
+            }
+
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            self.lex_file(input, path, &dir)?;
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
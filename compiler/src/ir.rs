@@ -0,0 +1,246 @@
+use virtual_machine::{decode::DecodedProgram, error::VmError, instruction::OpCode, vm::HEADER};
+
+use crate::{error::CompError, generator::Generator, token::Token};
+
+//a single instruction taken from parsed tokens, with jump and call targets given as instruction indices rather than addresses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub opcode: OpCode,
+
+    //opcode as it was written, e.g. "PUSH" or "Push"
+    pub word: String,
+    pub operand: Option<Token>,
+
+    //index of the instruction a jump or call goes to, which is the number of instructions when jumping to the end
+    pub target: Option<usize>,
+
+    //function labels placed right before the instruction
+    pub labels: Vec<String>,
+    pub line: usize,
+
+    //message given to an ASSERT or TRAP, which is kept in the data section
+    pub message: Option<String>
+}
+
+/*
+Parsed tokens grouped into instructions, which lets passes such as the optimizer add and remove instructions
+without working out jump distances and function addresses by hand.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstructionList {
+    pub instructions: Vec<Instruction>,
+
+    //labels after the last instruction
+    pub end_labels: Vec<String>
+}
+
+impl InstructionList {
+    //lines are given per token, as returned by Lexer::lex_with_lines
+    pub fn from_tokens(tokens: &[Token], lines: &[usize]) -> Result<InstructionList, CompError> {
+        let addresses = Generator::addresses(tokens);
+        let end_address = match tokens.last() {
+            Some(Token::NumU(_)) | Some(Token::NumI(_)) | Some(Token::NumF(_)) => addresses[tokens.len() - 1] + 8,
+            Some(Token::OpCode(_)) => addresses[tokens.len() - 1] + 1,
+            _ => addresses.last().copied().unwrap_or(HEADER.len())
+        };
+
+        let mut instructions: Vec<Instruction> = Vec::new();
+        let mut instruction_addresses = Vec::new();
+        let mut labels = Vec::new();
+        let mut index = 0;
+
+        while index < tokens.len() {
+            match &tokens[index] {
+                Token::Func(name) => labels.push(name.clone()),
+                Token::OpCode(word) => {
+                    let opcode = OpCode::from(word);
+                    let opcode_index = index;
+                    let operand = match tokens.get(index + 1) {
+                        Some(token) if token.is_num() => {
+                            index += 1;
+                            Some(token.clone())
+                        },
+                        _ => None
+                    };
+                    let message = match tokens.get(index + 1) {
+                        Some(Token::Text(text)) => {
+                            index += 1;
+                            Some(text.clone())
+                        },
+                        _ => None
+                    };
+
+                    instructions.push(Instruction {
+                        opcode,
+                        word: word.clone(),
+                        operand,
+                        target: None,
+                        labels: std::mem::take(&mut labels),
+                        line: lines.get(opcode_index).copied().unwrap_or(0),
+                        message
+                    });
+                    instruction_addresses.push(addresses[opcode_index]);
+                },
+                _ => return Err(CompError::UnexpectedChar("Numbers must only proceed words".to_string()))
+            }
+
+            index += 1;
+        }
+
+        let len = instructions.len();
+        for (i, instruction) in instructions.iter_mut().enumerate() {
+            let operand = match instruction.operand {
+                Some(Token::NumU(num)) => num,
+                _ => continue
+            };
+
+            //jumps are relative to the jump opcode, calls give the address right before the function
+            let target_address = match instruction.opcode {
+                OpCode::JMP | OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL => instruction_addresses[i] as i64 + i64::from_be_bytes(operand.to_be_bytes()),
+                OpCode::Call => operand as i64 + 1,
+                _ => continue
+            };
+
+            instruction.target = if target_address == end_address as i64 {
+                Some(len)
+            }
+            else {
+                match instruction_addresses.binary_search(&(target_address as usize)) {
+                    Ok(target) => Some(target),
+                    Err(_) => return Err(CompError::Impossible(format!("'{}' on line {} does not land on an instruction", instruction.word, instruction.line)))
+                }
+            };
+        }
+
+        Ok(InstructionList { instructions, end_labels: labels })
+    }
+
+    /*
+    Reads instructions back out of a compiled program.
+    Function names are not kept in the binary, so each function that is called is labelled with its address instead.
+    */
+    pub fn from_binary(program: &[u8]) -> Result<InstructionList, CompError> {
+        let decoded = match DecodedProgram::decode(program) {
+            Ok(decoded) => decoded,
+            Err(VmError::InvalidProgram(message)) => return Err(CompError::Impossible(message)),
+            Err(e) => return Err(CompError::Impossible(format!("{:?}", e)))
+        };
+
+        let mut instructions: Vec<Instruction> = decoded.instructions.iter().zip(&decoded.addresses).map(|(instruction, address)| {
+            let is_target = instruction.opcode == OpCode::Call || instruction.opcode == OpCode::JMP || instruction.opcode.is_conditional_jump();
+
+            Instruction {
+                opcode: instruction.opcode,
+                word: format!("{:?}", instruction.opcode),
+                operand: if instruction.opcode.operand_count() == 1 { Some(Token::NumU(instruction.operand)) } else { None },
+                target: if is_target { Some(instruction.target) } else { None },
+                labels: Vec::new(),
+                line: 0,
+                message: decoded.data.message(*address).map(String::from)
+            }
+        }).collect();
+
+        let mut end_labels = Vec::new();
+        for instruction in decoded.instructions.iter().filter(|instruction| instruction.opcode == OpCode::Call) {
+            let label = format!("fn@{}", instruction.operand + 1);
+            let labels = match instructions.get_mut(instruction.target) {
+                Some(target) => &mut target.labels,
+                None => &mut end_labels
+            };
+
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+
+        Ok(InstructionList { instructions, end_labels })
+    }
+
+    //Converts back to parsed tokens, working out jump distances and function addresses from the targets
+    pub fn to_tokens(&self) -> (Vec<Token>, Vec<usize>) {
+        let mut addresses = Vec::with_capacity(self.instructions.len() + 1);
+        let mut address = HEADER.len();
+        for instruction in &self.instructions {
+            addresses.push(address);
+            address += 1 + instruction.operand.is_some() as usize * 8;
+        }
+        addresses.push(address);
+
+        let mut tokens = Vec::new();
+        let mut lines = Vec::new();
+
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            for label in &instruction.labels {
+                tokens.push(Token::Func(label.clone()));
+                lines.push(instruction.line);
+            }
+
+            tokens.push(Token::OpCode(instruction.word.clone()));
+            lines.push(instruction.line);
+
+            let operand = match (instruction.target, instruction.opcode) {
+                (Some(target), OpCode::Call) => Some(Token::NumU(addresses[target] as u64 - 1)),
+                (Some(target), _) => Some(Token::NumU(u64::from_be_bytes((addresses[target] as i64 - addresses[i] as i64).to_be_bytes()))),
+                (None, _) => instruction.operand.clone()
+            };
+
+            if let Some(operand) = operand {
+                tokens.push(operand);
+                lines.push(instruction.line);
+            }
+
+            if let Some(message) = &instruction.message {
+                tokens.push(Token::Text(message.clone()));
+                lines.push(instruction.line);
+            }
+        }
+
+        for label in &self.end_labels {
+            tokens.push(Token::Func(label.clone()));
+            lines.push(self.instructions.last().map_or(0, |i| i.line));
+        }
+
+        (tokens, lines)
+    }
+
+    //whether anything other than falling through from the previous instruction can reach the instruction
+    pub fn is_entry_point(&self, index: usize) -> bool {
+        !self.instructions[index].labels.is_empty() || self.instructions.iter().any(|i| i.target == Some(index))
+    }
+
+    /*
+    Removes the instructions at the given indices.
+    Jumps, calls and labels which pointed at a removed instruction move on to the next instruction that is kept.
+    */
+    pub fn remove(&mut self, removed: &[bool]) {
+        let mut new_index = vec![0; self.instructions.len() + 1];
+        let mut kept = 0;
+        for (i, is_removed) in removed.iter().enumerate() {
+            new_index[i] = kept;
+            if !is_removed {
+                kept += 1;
+            }
+        }
+        new_index[self.instructions.len()] = kept;
+
+        let mut moved_labels = Vec::new();
+        let mut instructions = Vec::with_capacity(kept);
+
+        for (i, mut instruction) in std::mem::take(&mut self.instructions).into_iter().enumerate() {
+            instruction.target = instruction.target.map(|target| new_index[target]);
+
+            if removed[i] {
+                moved_labels.append(&mut instruction.labels);
+            }
+            else {
+                moved_labels.append(&mut instruction.labels);
+                instruction.labels = std::mem::take(&mut moved_labels);
+                instructions.push(instruction);
+            }
+        }
+
+        moved_labels.append(&mut self.end_labels);
+        self.end_labels = moved_labels;
+        self.instructions = instructions;
+    }
+}
@@ -1,10 +1,19 @@
-use std::{fmt::Error, process::Output};
+use std::collections::HashMap;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{constants::ConstEvaluator, error::CompError, expr::{self, ExprParser, TokenTarget}, macros::MacroExpander, structured::{ast::Type, scanner::Scanner}, token::Token};
 
-use crate::{error::CompError, token::Token};
 
 //converts human readable text into tokens
 pub struct Lexer;
 
+impl Default for Lexer {
+    fn default() -> Lexer {
+        Lexer::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Lexer{
 
@@ -12,9 +21,36 @@ impl Lexer {
     }
 
     pub fn lex<S: Into<String>>( input: S ) -> Result<Vec<Token>, CompError> {
-        let input: Vec<char> = input.into().chars().collect();
+        Ok(Lexer::lex_with_lines(input)?.0)
+    }
+
+    //same as lex but also returns the line (starting from 1) that each token was found on, with macros expanded
+    pub fn lex_with_lines<S: Into<String>>( input: S ) -> Result<(Vec<Token>, Vec<usize>), CompError> {
+        Lexer::lex_declared(input.into(), &mut HashMap::new())
+    }
+
+    //same as lex, with the variables declared by earlier input, which gains the variables that this input declares
+    pub fn lex_with_variables<S: Into<String>>( input: S, variables: &mut HashMap<String, Type> ) -> Result<Vec<Token>, CompError> {
+        let mut declared = variables.clone();
+        let (tokens, _) = Lexer::lex_declared(input.into(), &mut declared)?;
+        *variables = declared;
+        Ok(tokens)
+    }
+
+    //variables holds the type of each variable declared with .var, whose names are kept for the register allocator to replace
+    fn lex_declared(input: String, variables: &mut HashMap<String, Type>) -> Result<(Vec<Token>, Vec<usize>), CompError> {
+        let input: Vec<char> = input.chars().collect();
         let mut output = Vec::new();
+        let mut lines = Vec::new();
         let mut index = 0;
+        let mut line = 1;
+        let mut line_start = 0;
+
+        //values of constants defined so far, which replace their names wherever they are used
+        let mut constants: HashMap<String, Token> = HashMap::new();
+
+        //parameters of the macros defined so far and the line of each, which constants can't share a name with
+        let mut params: HashMap<String, usize> = HashMap::new();
 
         if input.is_empty() {
             return Err(CompError::UnexpectedEOF("Input is empty".into()));
@@ -25,25 +61,87 @@ impl Lexer {
                 break;
             }
 
+            //words and numbers consume the character after them, so newlines are counted up to the start of each token
+            line += input[line_start..index].iter().filter(|c| **c == '\n').count();
+            line_start = index;
+
             output.push(  
                 match input[index]{
-                    'a'..='z' | 'A'..='Z' => Lexer::get_word(&input, &mut index),
+                    'a'..='z' | 'A'..='Z' => {
+                        if let Some(tokens) = Lexer::get_assignment(&input, &mut index, line, variables, &constants)? {
+                            lines.extend(std::iter::repeat_n(line, tokens.len()));
+                            output.extend(tokens);
+                            continue
+                        }
 
-                    '0'..='9' | '+' | '-' => Lexer::get_num(&input, &mut index),
+                        match Lexer::get_word(&input, &mut index) {
+                            Token::OpCode(word) if constants.contains_key(&word) => constants[&word].clone(),
+                            token => token
+                        }
+                    },
+
+                    //directives such as .macro
+                    '.' if input.get(index + 1).is_some_and(|c| c.is_ascii_alphabetic()) => match Lexer::get_word(&input, &mut index) {
+                        Token::OpCode(word) if word == ".const" => {
+                            Lexer::get_const(&input, &mut index, line, &mut constants, &params)?;
+                            continue
+                        },
+                        Token::OpCode(word) if word == ".macro" => {
+                            Lexer::get_params(&input, index, line, &constants, &mut params)?;
+                            Token::OpCode(word)
+                        },
+                        Token::OpCode(word) if word == ".var" => {
+                            Lexer::get_var(&input, &mut index, line, variables)?;
+                            continue
+                        },
+                        token => token
+                    },
+
+                    //characters such as 'A' are their character code
+                    '\'' if input.get(index + 2) == Some(&'\'') => {
+                        index += 3;
+                        Token::NumU(input[index - 2] as u64)
+                    },
+
+                    '0'..='9' | '+' | '-' => Lexer::get_num(&input, &mut index, line)?,
 
-                    ' ' | '\t' | '\n' | _ => {
+                    //messages for ASSERT and TRAP
+                    '"' => Lexer::get_text(&input, &mut index, line)?,
+
+                    _ => {
                         index += 1;
                         continue
                     },
                 }
-            )
+            );
+            lines.push(line);
         };
 
-        return Ok(output)
+        //a label with the same name as a constant couldn't be called, as the constant would replace the name after CALL
+        for (token, line) in output.iter().zip(lines.iter()) {
+            if let Token::Func(name) = token {
+                if constants.contains_key(name) {
+                    return Err(CompError::UnexpectedChar(format!("Function '{}' on line {} has the same name as a constant", name, line)))
+                }
+            }
+        }
+
+        let (output, lines) = MacroExpander::expand(output, lines)?;
 
+        //macro arguments can be variables, so names after LOAD and STORE are only checked once macros are expanded
+        for (index, token) in output.iter().enumerate().skip(1) {
+            match token {
+                Token::OpCode(word) if Lexer::uses_register(&output[index - 1]) && !variables.contains_key(word) => {
+                    return Err(CompError::UnexpectedChar(format!("Variable '{}' on line {} isn't declared with .var", word, lines[index])))
+                },
+                _ => ()
+            }
+        }
+
+        Ok((output, lines))
     }
 
-    fn get_word(input: &Vec<char>, index: &mut usize) -> Token {
+    fn get_word(input: &[char], index: &mut usize) -> Token {
         let mut word = String::from(input[*index]);
         *index += 1;
 
@@ -69,7 +167,183 @@ impl Lexer {
         }
     }
 
-    pub fn get_num(input: &Vec<char>, index: &mut usize) -> Token {
+    //reads text in double quotes, which can't go past the end of the line, with \n, \t, \" and \\ as escapes
+    fn get_text(input: &[char], index: &mut usize, line: usize) -> Result<Token, CompError> {
+        let mut text = String::new();
+        *index += 1;
+
+        loop {
+            match input.get(*index) {
+                None | Some('\n') => return Err(CompError::UnexpectedEOF(format!("Text on line {} is missing its closing '\"'", line))),
+                Some('"') => {
+                    *index += 1;
+                    return Ok(Token::Text(text))
+                },
+                Some('\\') => {
+                    *index += 1;
+                    match input.get(*index) {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some(c @ ('"' | '\\')) => text.push(*c),
+                        _ => return Err(CompError::UnexpectedChar(format!("Unknown escape in text on line {}", line)))
+                    }
+                },
+                Some(c) => text.push(*c)
+            }
+
+            *index += 1;
+        }
+    }
+
+    //text written in double quotes, with the escapes get_text reads
+    pub fn quote(text: &str) -> String {
+        let mut output = String::from('"');
+        for c in text.chars() {
+            match c {
+                '\n' => output.push_str("\\n"),
+                '\t' => output.push_str("\\t"),
+                '"' | '\\' => {
+                    output.push('\\');
+                    output.push(c)
+                },
+                c => output.push(c)
+            }
+        }
+
+        output.push('"');
+        output
+    }
+
+    //reads NAME = expression up to the end of the line, after .const
+    fn get_const(input: &[char], index: &mut usize, line: usize, constants: &mut HashMap<String, Token>, params: &HashMap<String, usize>) -> Result<(), CompError> {
+        let text = Lexer::rest_of_line(input, index);
+        let (name, expression) = match text.split_once('=') {
+            Some((name, expression)) => (name.trim(), expression),
+            None => return Err(CompError::UnexpectedChar(format!("Constant on line {} must be written as .const NAME = value", line)))
+        };
+
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) || name.contains(char::is_whitespace) {
+            return Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a valid constant name", name, line)))
+        }
+
+        if constants.contains_key(name) {
+            return Err(CompError::UnexpectedChar(format!("Constant '{}' on line {} is already defined", name, line)))
+        }
+
+        //constants replace every word with their name, so they can't take the name of an opcode or a macro parameter
+        if OpCode::from(&name.to_string()) != OpCode::Illegal {
+            return Err(CompError::UnexpectedChar(format!("Constant '{}' on line {} has the same name as an opcode", name, line)))
+        }
+
+        if let Some(param_line) = params.get(name) {
+            return Err(CompError::UnexpectedChar(format!("Constant '{}' on line {} has the same name as a parameter of the macro on line {}", name, line, param_line)))
+        }
+
+        let value = ConstEvaluator::evaluate(expression, constants).map_err(|e| e.context(&format!("in constant '{}' on line {}", name, line)))?;
+
+        constants.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    //records the parameters of a macro, which are the words after its name on the rest of the line after .macro, without reading them
+    fn get_params(input: &[char], index: usize, line: usize, constants: &HashMap<String, Token>, params: &mut HashMap<String, usize>) -> Result<(), CompError> {
+        let mut end = index;
+        for param in Lexer::rest_of_line(input, &mut end).split_whitespace().skip(1) {
+            if constants.contains_key(param) {
+                return Err(CompError::UnexpectedChar(format!("Parameter '{}' of the macro on line {} has the same name as a constant", param, line)))
+            }
+            params.insert(param.to_string(), line);
+        }
+
+        Ok(())
+    }
+
+    //reads NAME or NAME: TYPE up to the end of the line, after .var
+    fn get_var(input: &[char], index: &mut usize, line: usize, variables: &mut HashMap<String, Type>) -> Result<(), CompError> {
+        let text = Lexer::rest_of_line(input, index);
+        let (name, ty) = match text.split_once(':') {
+            Some((name, ty)) => match Type::from_name(ty.trim()) {
+                Some(ty) => (name.trim(), ty),
+                None => return Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a type (u64, i64 or f64)", ty.trim(), line)))
+            },
+            None => (text.trim(), Type::U64)
+        };
+
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) || name.contains(char::is_whitespace) {
+            return Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a valid variable name", name, line)))
+        }
+
+        if variables.contains_key(name) {
+            return Err(CompError::UnexpectedChar(format!("Variable '{}' on line {} is already defined", name, line)))
+        }
+
+        variables.insert(name.to_string(), ty);
+        Ok(())
+    }
+
+    /*
+    Reads NAME = expression, returning the tokens that calculate the expression and store it.
+    A name that hasn't been declared with .var is declared by its first assignment as a u64 variable.
+    Nothing is read when the line is anything else.
+    */
+    fn get_assignment(input: &[char], index: &mut usize, line: usize, variables: &mut HashMap<String, Type>, constants: &HashMap<String, Token>) -> Result<Option<Vec<Token>>, CompError> {
+        let mut end = *index;
+        let text = Lexer::rest_of_line(input, &mut end);
+
+        let (name, expression) = match text.split_once('=') {
+            Some((name, expression)) => (name.trim(), expression),
+            None => return Ok(None)
+        };
+
+        let ty = match variables.get(name) {
+            Some(ty) => *ty,
+            None if Lexer::is_new_variable(name, constants) => {
+                variables.insert(name.to_string(), Type::U64);
+                Type::U64
+            },
+            None => return Ok(None)
+        };
+        *index = end;
+
+        //blank lines are added in front so that errors are reported on the right line
+        let tokens = Scanner::scan(&format!("{}{}", "\n".repeat(line - 1), expression))?;
+        let mut position = 0;
+        let value = ExprParser::parse(&tokens, &mut position)?;
+
+        if let Some((token, _)) = tokens.get(position) {
+            return Err(CompError::UnexpectedChar(format!("Unexpected {} after the value of '{}' on line {}", token.describe(), name, line)))
+        }
+
+        let mut target = TokenTarget { tokens: Vec::new(), variables, constants };
+        expr::lower(&mut target, &value, ty)?;
+        target.tokens.push(Token::OpCode("STORE".into()));
+        target.tokens.push(Token::OpCode(name.to_string()));
+
+        Ok(Some(target.tokens))
+    }
+
+    //whether the name before = can be declared by assigning to it, which excludes anything that could already mean something else on that line
+    fn is_new_variable(name: &str, constants: &HashMap<String, Token>) -> bool {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !constants.contains_key(name)
+            && OpCode::from(&name.to_string()) == OpCode::Illegal
+    }
+
+    fn rest_of_line(input: &[char], index: &mut usize) -> String {
+        let start = *index;
+        while *index < input.len() && input[*index] != '\n' {
+            *index += 1;
+        }
+
+        input[start..*index].iter().collect()
+    }
+
+    fn uses_register(token: &Token) -> bool {
+        matches!(token, Token::OpCode(word) if matches!(word.as_str(), "Load" | "LOAD" | "Store" | "STORE"))
+    }
+
+    pub fn get_num(input: &[char], index: &mut usize, line: usize) -> Result<Token, CompError> {
         let mut num = String::from(input[*index]);
         *index += 1;
         
@@ -90,22 +364,20 @@ impl Lexer {
            }
         }
 
-        match num.parse::<u64>() {
-            Ok(n) => return Token::NumU(n),
-            Err(_) => ()
-        };
+        if let Ok(n) = num.parse::<u64>() {
+            return Ok(Token::NumU(n))
+        }
 
-        match num.parse::<i64>() {
-            Ok(n) => return Token::NumI(n),
-            Err(_) => ()
-        };
+        if let Ok(n) = num.parse::<i64>() {
+            return Ok(Token::NumI(n))
+        }
 
-        match num.parse::<f64>() {
-            Ok(n) => return Token::NumF(n),
-            Err(_) => ()
-        };
+        if let Ok(n) = num.parse::<f64>() {
+            return Ok(Token::NumF(n))
+        }
 
-        unreachable!()
+        //a sign on its own or a number with two decimal points
+        Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a number", num, line)))
 
     }
 
@@ -1,9 +1,27 @@
 pub mod error;
 pub mod token;
 pub mod lexer;
+pub mod constants;
+pub mod macros;
 pub mod parser;
 pub mod generator;
+pub mod include;
 pub mod compiler;
+pub mod structured;
+pub mod expr;
+pub mod regalloc;
+pub mod listing;
+pub mod program;
+pub mod disasm;
+pub mod object;
+pub mod linker;
+pub mod ir;
+pub mod folder;
+pub mod cfg;
+pub mod dot;
+pub mod optimizer;
+pub mod coverage;
+pub mod testing;
 extern crate virtual_machine;
 
 
@@ -16,7 +34,25 @@ mod compiler_tests {
     use crate::lexer::*;
     use crate::token::*;
     use crate::compiler::*;
-    use virtual_machine::vm::HEADER;
+    use crate::coverage::CoverageReport;
+    use crate::optimizer::Optimizer;
+    use crate::folder::Folder;
+    use crate::ir::InstructionList;
+    use crate::cfg::ControlFlowGraph;
+    use crate::error::{CompError, CompWarning};
+    use crate::dot::Dot;
+    use crate::include::Includer;
+    use crate::object::{ObjectFile, RelocationKind};
+    use crate::linker::Linker;
+    use crate::regalloc::{Location, RegisterAllocator};
+    use crate::disasm::Disassembler;
+    use crate::testing::{TestResult, TestSuite};
+    use std::fs;
+    use virtual_machine::instruction::OpCode;
+    use virtual_machine::vm::{HEADER, Machine};
+    use virtual_machine::output::SharedBuffer;
+    use virtual_machine::error::VmError;
+    use virtual_machine::data::DataSection;
 
 
 
@@ -48,7 +84,7 @@ mod compiler_tests {
     #[test]
     fn basic_generating() {
         let program = vec![Token::OpCode("Push".to_string()), Token::NumU(3),  Token::OpCode("PUSH".to_string()), Token::NumF(-2.1), Token::OpCode("ADDF".to_string())];
-        let mut binary_code: Vec<u8> = [HEADER.to_vec(), [2, 0, 0, 0, 0, 0, 0 ,0 ,3, 2, 192, 0, 204, 204, 204, 204, 204, 205, 12].to_vec()].concat();
+        let binary_code: Vec<u8> = [HEADER.to_vec(), [2, 0, 0, 0, 0, 0, 0 ,0 ,3, 2, 192, 0, 204, 204, 204, 204, 204, 205, 12].to_vec()].concat();
 
 
 
@@ -59,7 +95,7 @@ mod compiler_tests {
 
     #[test]
     fn basic_compiling() {
-        let mut comp = Compiler::new("../nar files/test.nar".to_string());
+        let mut comp = Compiler::default();
         let program = "
             PUSH 2.0
             PUSH 2.5
@@ -68,7 +104,7 @@ mod compiler_tests {
             DIVF
         ";
 
-        assert_eq!(Ok(()), comp.compile(program, true))
+        assert_eq!(Ok(()), comp.build(program, true).map(|_| ()))
     }
 
     #[test]
@@ -85,7 +121,11 @@ mod compiler_tests {
 
         let output = Lexer::lex(program).unwrap();
 
-        assert_eq!(vec![Token::OpCode("PUSH".to_string()), Token::NumI(-21), Token::OpCode("ADDF".to_string()), Token::Func("my_func".to_string()), Token::OpCode("PUSH".to_string()), Token::NumU(2), Token::OpCode("RETURN".to_string()), ], output)
+        assert_eq!(vec![Token::OpCode("PUSH".to_string()), Token::NumI(-21), Token::OpCode("ADDF".to_string()), Token::Func("my_func".to_string()), Token::OpCode("PUSH".to_string()), Token::NumU(2), Token::OpCode("RETURN".to_string()), ], output);
+
+        //numbers that can't be read are errors rather than panics
+        assert_eq!(Err(CompError::UnexpectedChar("'-' on line 1 is not a number".into())), Lexer::lex("PUSH -"));
+        assert_eq!(Err(CompError::UnexpectedChar("'1.2.3' on line 2 is not a number".into())), Lexer::lex("PUSH 1\nPUSH 1.2.3\n"));
     }
 
     #[test]
@@ -100,7 +140,7 @@ mod compiler_tests {
 
     #[test]
     fn compile_func1() {
-        let mut comp = Compiler::new("../nar files/fizzbuzz.nar".to_string());
+        let mut comp = Compiler::default();
 
         let program = "
             PUSH 30
@@ -130,12 +170,12 @@ mod compiler_tests {
             RETURN
         ";
 
-        assert_eq!(Ok(()), comp.compile(program, true))
+        assert_eq!(Ok(()), comp.build(program, true).map(|_| ()))
     }
 
     #[test]
     fn compile_func2() {
-        let mut comp = Compiler::new("../nar files/fizzbuzz.bin".to_string());
+        let mut comp = Compiler::default();
 
         let program = "
             PUSH 15
@@ -207,6 +247,846 @@ mod compiler_tests {
             RETURN
         ";
 
-        assert_eq!(Ok(()), comp.compile(program, true))
+        assert_eq!(Ok(()), comp.build(program, true).map(|_| ()))
+    }
+
+    #[test]
+    fn coverage_report() {
+        let program = "PUSH 9
+CALL divisible_3
+HALT
+
+divisible_3:
+    PUSH 3
+    MODU
+    PUSH 0
+    CMP 0
+    JNE 4
+    PUSH 1
+    STORE 1
+RETURN
+    PUSH 0
+    STORE 1
+RETURN
+";
+
+        let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+        let tokens = Parser::parse(tokens).unwrap();
+        let line_table = Generator::line_table(&tokens, &lines);
+        let binary = Generator::generate(tokens).unwrap();
+
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(SharedBuffer::new()));
+        machine.enable_coverage();
+        machine.run(binary.clone(), false);
+        let coverage = machine.take_coverage().unwrap();
+
+        let lcov = CoverageReport::lcov(&coverage, &binary, &line_table, "divisible.nar");
+        let expected = "TN:
+SF:divisible.nar
+BRDA:10,0,0,0
+BRDA:10,0,1,1
+BRF:2
+BRH:1
+DA:1,1
+DA:2,1
+DA:3,1
+DA:6,1
+DA:7,1
+DA:8,1
+DA:9,1
+DA:10,1
+DA:11,1
+DA:12,1
+DA:13,1
+DA:14,0
+DA:15,0
+DA:16,0
+LF:14
+LH:11
+end_of_record
+";
+        assert_eq!(expected, lcov);
+
+        let annotated = CoverageReport::annotate(&coverage, &binary, &line_table, program);
+        let annotated: Vec<&str> = annotated.lines().collect();
+        assert_eq!("        1 |     JNE 4    [taken 0, not taken 1]", annotated[9]);
+        assert_eq!("    ##### |     PUSH 0", annotated[13]);
+        assert_eq!("          | divisible_3:", annotated[4]);
+    }
+
+    fn run_captured(binary: Vec<u8>) -> (u64, String) {
+        let output = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(output.clone()));
+        let result = machine.run(binary, false);
+        (result, output.to_string_lossy())
+    }
+
+    #[test]
+    fn peephole_optimizing() {
+        let program = "
+            PUSH 4
+            PUSH 7
+            POP
+            PUSH 0
+            ADDU
+            STORE 0
+            LOAD 0
+            JMP 1
+            CALL double
+            HALT
+
+            double:
+            DUPLI
+            DUPLI
+            POP
+            ADDU
+            PRINT 0
+            RETURN
+        ";
+
+        let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+        let tokens = Parser::parse(tokens).unwrap();
+        let unoptimized = Generator::generate(tokens.clone()).unwrap();
+
+        let (tokens, _) = Optimizer::optimize(tokens, lines).unwrap();
+        let ops: Vec<&Token> = tokens.iter().filter(|t| matches!(t, Token::OpCode(_))).collect();
+        assert_eq!(vec!["PUSH", "DUPLI", "STORE", "CALL", "HALT", "DUPLI", "ADDU", "PRINT", "RETURN"], ops.iter().map(|t| match t { Token::OpCode(w) => w.as_str(), _ => "" }).collect::<Vec<&str>>());
+
+        let optimized = Generator::generate(tokens).unwrap();
+        assert!(optimized.len() < unoptimized.len());
+        assert_eq!("8\n", run_captured(optimized.clone()).1);
+        assert_eq!(run_captured(unoptimized), run_captured(optimized));
+    }
+
+    #[test]
+    fn optimizing_keeps_jump_targets() {
+        //the jump back lands on the DUPLI of a DUPLI POP pair, which must not be removed
+        let program = "
+            PUSH 3
+            DUPLI
+            POP
+            PUSH 1
+            SUBU
+            DUPLI
+            PRINT 0
+            DUPLI
+            PUSH 0
+            CMP 0
+            JNE -9
+            HALT
+        ";
+
+        let source = include_str!("../../nar files/fizzbuzz.nar");
+        for program in [program, source].iter() {
+            let (tokens, lines) = Lexer::lex_with_lines(*program).unwrap();
+            let tokens = Parser::parse(tokens).unwrap();
+            let unoptimized = Generator::generate(tokens.clone()).unwrap();
+            let (tokens, _) = Optimizer::optimize(tokens, lines).unwrap();
+            let optimized = Generator::generate(tokens).unwrap();
+
+            assert_eq!(run_captured(unoptimized), run_captured(optimized));
+        }
+    }
+
+    #[test]
+    fn optimizing_keeps_faults() {
+        //each pair needs a value that isn't on the stack, so removing it would hide the fault
+        let programs = [
+            ("DUPLI\nPOP\nHALT", 17),
+            ("PUSH 0\nADDU\nHALT", 26),
+            ("PUSH 1\nDIVU\nHALT", 26),
+            ("CALL sub\nHALT\n\nsub:\nPUSH 0\nSUBU\nRETURN", 36),
+        ];
+
+        for (program, address) in programs {
+            let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+            let tokens = Parser::parse(tokens).unwrap();
+            let (optimized, _) = Optimizer::optimize(tokens.clone(), lines).unwrap();
+            assert_eq!(tokens, optimized);
+
+            let mut machine = Machine::new();
+            assert!(machine.load(Generator::generate(optimized).unwrap()));
+            assert_eq!(Err(VmError::Fault(format!("Not enough numbers on the stack at address {}", address))), machine.resume_with_fuel(u64::MAX));
+        }
+    }
+
+    #[test]
+    fn constant_folding() {
+        let program = "
+            PUSH 6
+            PUSH 5
+            MULU
+            PUSH 2
+            SHIFT 0
+            PUSH -7
+            PUSH 2
+            DIVI
+            PUSH 1.5
+            PUSH 0.25
+            SUBF
+            PRINT 2
+            PRINT 1
+            PRINT 0
+            HALT
+        ";
+
+        let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+        let tokens = Parser::parse(tokens).unwrap();
+        let unoptimized = Generator::generate(tokens.clone()).unwrap();
+        let (tokens, _) = Optimizer::optimize(tokens, lines).unwrap();
+
+        assert_eq!(Token::NumU(120), tokens[1]);
+        assert_eq!(Token::NumI(-3), tokens[3]);
+        assert_eq!(Token::NumF(1.25), tokens[5]);
+        assert_eq!(run_captured(unoptimized), run_captured(Generator::generate(tokens).unwrap()));
+    }
+
+    #[test]
+    fn folding_leaves_overflow() {
+        //these would set Flag::Overflow or panic in the vm, so they are left for it to run
+        assert_eq!(None, Folder::evaluate(OpCode::AddU, false, u64::MAX, 1));
+        assert_eq!(None, Folder::evaluate(OpCode::SubU, false, 1, 2));
+        assert_eq!(None, Folder::evaluate(OpCode::DivI, false, i64::MIN as u64, -1i64 as u64));
+        assert_eq!(None, Folder::evaluate(OpCode::ModU, false, 1, 0));
+        assert_eq!(None, Folder::evaluate(OpCode::Shift, true, 1, 64));
+        assert_eq!(Some(Token::NumU(1 << 63)), Folder::evaluate(OpCode::Shift, true, 1, 63));
+
+        let program = "
+            PUSH 0
+            PUSH 1
+            SUBU
+            HALT
+        ";
+        let tokens = Parser::parse(Lexer::lex(program).unwrap()).unwrap();
+        let (optimized, _) = Optimizer::optimize(tokens.clone(), vec![0; tokens.len()]).unwrap();
+        assert_eq!(tokens, optimized);
+    }
+
+    #[test]
+    fn dead_code() {
+        let program = "PUSH 4
+CALL double
+HALT
+PUSH 1
+
+double:
+    DUPLI
+    ADDU
+    RETURN
+    PRINT 0
+
+unused:
+    PUSH 2
+    RETURN
+";
+
+        let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+        let tokens = Parser::parse(tokens).unwrap();
+        let list = InstructionList::from_tokens(&tokens, &lines).unwrap();
+        let warnings = ControlFlowGraph::new(&list).warnings(&list);
+
+        assert_eq!(vec![
+            CompWarning::UnreachableCode("Instructions on line 4 can never run".to_string()),
+            CompWarning::UnreachableCode("Instructions on line 10 can never run".to_string()),
+            CompWarning::UnusedFunction("Function 'unused' on lines 13-14 is never called".to_string())
+        ], warnings);
+
+        let unoptimized = Generator::generate(tokens.clone()).unwrap();
+        let (tokens, _) = Optimizer::optimize(tokens, lines).unwrap();
+        assert!(!tokens.contains(&Token::Func("unused".to_string())));
+        assert_eq!(6, tokens.iter().filter(|t| matches!(t, Token::OpCode(_))).count());
+
+        let optimized = Generator::generate(tokens).unwrap();
+        assert_eq!(run_captured(unoptimized), run_captured(optimized));
+    }
+
+    #[test]
+    fn control_flow_dot() {
+        let program = "
+            PUSH 3
+            CALL countdown
+            HALT
+
+            countdown:
+            PUSH 1
+            SUBU
+            DUPLI
+            PUSH 0
+            CMP 0
+            JNE -5
+            RETURN
+        ";
+
+        let expected = "digraph program {
+    node [shape=box, fontname=\"monospace\"];
+    subgraph cluster_0 {
+        label=\"main\";
+        b0 [label=\"PUSH 3\\lCALL countdown\\lHALT\\l\"];
+    }
+    subgraph cluster_1 {
+        label=\"countdown\";
+        b1 [label=\"PUSH 1\\lSUBU\\lDUPLI\\lPUSH 0\\lCMP 0\\lJNE b1\\l\"];
+        b2 [label=\"RETURN\\l\"];
+    }
+    b0 -> b1 [style=dashed, label=\"call\"];
+    b1 -> b1 [label=\"not equal\"];
+    b1 -> b2 [label=\"otherwise\"];
+}
+";
+        assert_eq!(expected, Dot::from_source(program).unwrap());
+        let expected_source = expected;
+
+        //compiled programs no longer have function names
+        let binary = Generator::generate(Parser::parse(Lexer::lex(program).unwrap()).unwrap()).unwrap();
+        let expected = expected.replace("countdown", "fn@36").replace("PUSH", "Push").replace("CALL", "Call").replace("HALT", "Halt")
+            .replace("SUBU", "SubU").replace("DUPLI", "Dupli").replace("RETURN", "Return");
+        assert_eq!(expected, Dot::from_binary(&binary).unwrap());
+
+        //files are graphed as they are compiled, with included code and without test blocks
+        let dir = std::env::temp_dir().join(format!("nariva_dot_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.nar"), ".include \"count.nar\"\nPUSH 3\nCALL countdown\n\ntest \"counts\":\n    PUSH 1\n    ASSERT_EQ 1\n").unwrap();
+        fs::write(dir.join("count.nar"), "countdown:\nPUSH 1\nSUBU\nDUPLI\nPUSH 0\nCMP 0\nJNE -5\nRETURN\n").unwrap();
+        assert_eq!(expected_source, Dot::from_file(dir.join("main.nar")).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+
+        let graph = Dot::from_file("../nar files/fizzbuzz.nrs").unwrap();
+        assert!(graph.starts_with("digraph program {") && graph.contains("label=\"divisible\""));
+    }
+
+    #[test]
+    fn macro_expansion() {
+        let program = ".macro IS_ZERO reg
+    LOAD reg
+    PUSH 0
+    CMP 0
+.endm
+
+.macro PRINT_CHAR char
+    PUSH char
+    CALL print
+    JMP 3
+    print:
+    PRINT 3
+    RETURN
+.endm
+
+PUSH 0
+STORE 1
+IS_ZERO 1
+JNE 2
+PRINT_CHAR 89
+PRINT_CHAR 78
+HALT
+";
+
+        let (tokens, lines) = Lexer::lex_with_lines(program).unwrap();
+        assert_eq!(Token::OpCode("LOAD".to_string()), tokens[4]);
+        assert_eq!(Token::NumU(1), tokens[5]);
+        assert_eq!(18, lines[5]);
+        assert!(tokens.contains(&Token::Func("print.1".to_string())) && tokens.contains(&Token::Func("print.2".to_string())));
+
+        let binary = Generator::generate(Parser::parse(tokens).unwrap()).unwrap();
+        assert_eq!("Y\nN\n", run_captured(binary).1);
+    }
+
+    #[test]
+    fn macro_errors() {
+        let program = ".macro IS_ZERO reg
+    LOAD reg
+    PUSH 0
+    CMPP 0
+.endm
+PUSH 1
+IS_ZERO 1
+";
+        assert_eq!(
+            Err(CompError::UnexpectedChar("'CMPP' opcode doesn't exist, in macro 'IS_ZERO' defined on line 1 (line 4), used on line 7".to_string())),
+            Lexer::lex(program)
+        );
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Macro 'IS_ZERO' defined on line 1 takes 1 argument(s), used on line 7".to_string())),
+            Lexer::lex(program.replace("IS_ZERO 1", "IS_ZERO 1 2"))
+        );
+
+        assert_eq!(
+            Err(CompError::UnexpectedEOF("Macro 'IS_ZERO' on line 1 is missing '.endm'".to_string())),
+            Lexer::lex(program.replace(".endm", ""))
+        );
+    }
+
+    #[test]
+    fn named_constants() {
+        let program = ".const COUNTER = 1
+.const LIMIT = (3 + 2) * 4 - 5
+.const LETTER = 'F' + 1
+.const BELOW = COUNTER - LIMIT
+.const HALF = LIMIT / 2.0
+
+PUSH LIMIT
+STORE COUNTER
+PUSH LETTER
+PRINT 3
+PUSH 'A'
+PUSH BELOW
+PUSH HALF
+";
+
+        let tokens = Lexer::lex(program).unwrap();
+        assert_eq!(vec![
+            Token::OpCode("PUSH".to_string()), Token::NumU(15),
+            Token::OpCode("STORE".to_string()), Token::NumU(1),
+            Token::OpCode("PUSH".to_string()), Token::NumU(71),
+            Token::OpCode("PRINT".to_string()), Token::NumU(3),
+            Token::OpCode("PUSH".to_string()), Token::NumU(65),
+            Token::OpCode("PUSH".to_string()), Token::NumI(-14),
+            Token::OpCode("PUSH".to_string()), Token::NumF(7.5)
+        ], tokens);
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Constant 'MISSING' doesn't exist in constant 'A' on line 2".to_string())),
+            Lexer::lex(".const B = 1\n.const A = MISSING + 1\nPUSH A")
+        );
+        assert_eq!(
+            Err(CompError::Impossible("Division by zero in expression in constant 'A' on line 1".to_string())),
+            Lexer::lex(".const A = 4 / (2 - 2)\nPUSH A")
+        );
+
+        //names that would replace opcodes, labels or macro parameters are rejected
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Constant 'LOAD' on line 1 has the same name as an opcode".to_string())),
+            Lexer::lex(".const LOAD = 3\nLOAD 0")
+        );
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Function 'TOP' on line 3 has the same name as a constant".to_string())),
+            Lexer::lex(".const TOP = 3\nCALL TOP\nTOP:\nRETURN")
+        );
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Parameter 'N' of the macro on line 2 has the same name as a constant".to_string())),
+            Lexer::lex(".const N = 3\n.macro show N\nPUSH N\n.endm")
+        );
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Constant 'N' on line 4 has the same name as a parameter of the macro on line 1".to_string())),
+            Lexer::lex(".macro show N\nPUSH N\n.endm\n.const N = 3")
+        );
+    }
+
+    #[test]
+    fn including_files() {
+        let dir = std::env::temp_dir().join(format!("nariva_include_{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.nar"), ".include \"lib/helpers.nar\"\n.include \"shared.nar\"\nPUSH 4\nCALL double\nCALL print\nHALT\n").unwrap();
+        fs::write(dir.join("lib/helpers.nar"), ".include \"../shared.nar\"\n\ndouble:\n    DUPLI\n    ADDU\n    RETURN\n").unwrap();
+        fs::write(dir.join("shared.nar"), "print:\n    DUPLI\n    PRINT 0\n    RETURN\n").unwrap();
+
+        let source = Includer::lex_path(dir.join("main.nar")).unwrap();
+        assert_eq!(3, source.paths.len());
+        assert_eq!(1, source.tokens.iter().filter(|t| **t == Token::Func("print".to_string())).count());
+        assert_eq!((vec![0; 7], vec![3, 3, 4, 4, 5, 5, 6]), (source.files[..7].to_vec(), source.lines[..7].to_vec()));
+
+        let mut comp = Compiler::new(dir.join("main.binar").display().to_string());
+        comp.compile_file(dir.join("main.nar"), false).unwrap();
+        assert_eq!((8, "8\n".to_string()), run_captured(comp.get_output().clone()));
+        assert_eq!(4, comp.get_line_table().len());
+        assert_eq!(source.paths, comp.get_program().files);
+
+        fs::write(dir.join("shared.nar"), ".include \"main.nar\"\nprint:\n    RETURN\n").unwrap();
+        let cycle = format!("Files include each other on line 1 of 'shared.nar': {} -> {} -> {} -> {}",
+            dir.join("main.nar").canonicalize().unwrap().display(), dir.join("lib/helpers.nar").canonicalize().unwrap().display(),
+            dir.join("shared.nar").canonicalize().unwrap().display(), dir.join("main.nar").canonicalize().unwrap().display());
+        assert_eq!(Err(CompError::Impossible(cycle)), Includer::lex_path(dir.join("main.nar")));
+
+        fs::write(dir.join("lib/helpers.nar"), ".include \"missing.nar\"\n").unwrap();
+        match Includer::lex_path(dir.join("main.nar")) {
+            Err(CompError::Io(e)) => assert!(e.starts_with("Cannot include 'missing.nar' on line 1 of 'helpers.nar'")),
+            other => panic!("{:?}", other)
+        }
+
+        match Compiler::default().build_file(dir.join("absent.nar"), false) {
+            Err(CompError::Io(e)) => assert!(e.starts_with("Cannot read")),
+            other => panic!("{:?}", other)
+        }
+
+        //a program without HALT stops before the included code instead of running into it
+        fs::write(dir.join("main.nar"), ".include \"shared.nar\"\nPUSH 4\nCALL print\n").unwrap();
+        fs::write(dir.join("shared.nar"), "print:\n    DUPLI\n    PRINT 0\n    RETURN\n").unwrap();
+        let mut comp = Compiler::default();
+        assert_eq!((4, "4\n".to_string()), run_captured(comp.build_file(dir.join("main.nar"), false).unwrap().bytes.clone()));
+
+        //errors found while parsing name the file they are in
+        fs::write(dir.join("shared.nar"), "print:\n    FOO\n    RETURN\n").unwrap();
+        assert_eq!(Err(CompError::UnexpectedChar("'FOO' opcode doesn't exist on line 2 of 'shared.nar'".into())), comp.build_file(dir.join("main.nar"), false).cloned());
+
+        fs::write(dir.join("main.nar"), ".includes \"shared.nar\"\nPUSH 4\n").unwrap();
+        assert!(comp.build_file(dir.join("main.nar"), false).is_err());
+
+        //variables belong to the file that declares them, so the included x doesn't overwrite the x of main.nar
+        fs::write(dir.join("main.nar"), ".include \"shared.nar\"\n.var x\nPUSH 5\nSTORE x\nCALL bump\nLOAD x\nHALT\n").unwrap();
+        fs::write(dir.join("shared.nar"), ".var x\nbump:\n    PUSH 9\n    STORE x\n    RETURN\n").unwrap();
+        assert_eq!(5, run_captured(comp.build_file(dir.join("main.nar"), false).unwrap().bytes.clone()).0);
+        let names: Vec<&str> = comp.get_allocation().variables.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(vec!["x", "x in 'shared.nar'"], names);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn linking_objects() {
+        let main = ObjectFile::compile("
+            PUSH 4
+            CALL double
+            CALL show
+            CALL show
+            HALT
+
+            show:
+            CALL print
+            RETURN
+        ").unwrap();
+        let library = ObjectFile::compile("
+            print:
+            DUPLI
+            PRINT 0
+            RETURN
+
+            double:
+            DUPLI
+            ADDU
+            RETURN
+        ").unwrap();
+
+        assert_eq!(vec!["double".to_string(), "print".to_string()], main.imports);
+        assert_eq!(vec![("print".to_string(), 0), ("double".to_string(), 11)], library.exports);
+        assert_eq!(main, ObjectFile::from_bytes(&main.to_bytes()).unwrap());
+
+        let binary = Linker::link(&[main.clone(), library.clone()]).unwrap();
+        assert_eq!((8, "8\n8\n".to_string()), run_captured(binary));
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Function 'double' used by object 0 isn't defined in any object".to_string())),
+            Linker::link(std::slice::from_ref(&main))
+        );
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Function 'print' is defined in both object 1 and object 2".to_string())),
+            Linker::link(&[main.clone(), library.clone(), library.clone()])
+        );
+
+        //damaged objects are errors rather than panicking the linker
+        let mut past_end = main.clone();
+        past_end.relocations[0].offset = past_end.code.len() - 4;
+        assert!(ObjectFile::from_bytes(&past_end.to_bytes()).is_err());
+        assert!(Linker::link(&[past_end, library.clone()]).is_err());
+
+        let mut before_start = main.clone();
+        let local = before_start.relocations.iter().find(|relocation| relocation.kind == RelocationKind::Local).unwrap().offset;
+        before_start.code[local..local + 8].fill(0);
+        assert!(ObjectFile::from_bytes(&before_start.to_bytes()).is_err());
+        assert!(Linker::link(&[before_start, library]).is_err());
+    }
+
+    #[test]
+    fn structured_fizzbuzz() {
+        let binary = Generator::generate(crate::structured::compile_to_instructions(include_str!("../../nar files/fizzbuzz.nrs")).unwrap().to_tokens().0).unwrap();
+        let expected = run_captured(include_bytes!("../../nar files/fizzbuzz.binar").to_vec()).1;
+
+        assert_eq!(expected, run_captured(binary).1);
+    }
+
+    #[test]
+    fn structured_functions() {
+        let program = "
+            // recursion keeps each call's variables apart
+            fn factorial(n: i64) -> i64 {
+                if n <= 1 {
+                    return 1;
+                }
+                return n * factorial(n - 1);
+            }
+
+            fn half(x: f64) -> f64 {
+                return x / 2;
+            }
+
+            fn show(value: i64) {
+                print(value);
+            }
+
+            let total: i64 = factorial(5) - 200;
+            show(total);
+            print(-half(5.0) * 3);
+        ";
+
+        let binary = Generator::generate(crate::structured::compile_to_instructions(program).unwrap().to_tokens().0).unwrap();
+        assert_eq!("-80\n-7.5\n", run_captured(binary).1);
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("u64 values on line 1 cannot be negative".to_string())),
+            crate::structured::compile_to_instructions("let x: u64 = -1;")
+        );
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Function 'missing' on line 2 doesn't exist".to_string())),
+            crate::structured::compile_to_instructions("let x: u64 = 1;\nx = missing(x);")
+        );
+    }
+
+    #[test]
+    fn infix_expressions() {
+        let program = "
+            .const N = 7
+            a = 4
+            b = 5
+            x = (a + b) * 3 % N
+            LOAD x
+            PRINT 0
+            x = a + b * 2 - 10
+            LOAD x
+            PRINT 0
+            HALT
+        ";
+
+        let tokens = Lexer::lex(program).unwrap();
+        let op = |word: &str| Token::OpCode(word.to_string());
+        assert_eq!(
+            vec![op("LOAD"), op("a"), op("LOAD"), op("b"), op("ADDU"), op("PUSH"), Token::NumU(3), op("MULU"), op("PUSH"), Token::NumU(7), op("MODU"), op("STORE"), op("x")],
+            tokens[8..21].to_vec()
+        );
+
+        let (tokens, _) = RegisterAllocator::allocate(tokens).unwrap();
+        let binary = Generator::generate(Parser::parse(tokens).unwrap()).unwrap();
+        assert_eq!("6\n4\n", run_captured(binary).1);
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Expected ')' on line 2 but found '3'".to_string())),
+            Lexer::lex(".var x\nx = (1 + 2 3")
+        );
+
+        //only an assignment declares a variable, so one that is read first still has to be declared
+        assert_eq!(Err(CompError::UnexpectedChar("'y' on line 1 is not a variable or constant".to_string())), Lexer::lex("x = y + 1"));
+
+        //a line with = that doesn't start with a name isn't an assignment
+        assert_eq!(Some(&op("ASSERT")), Lexer::lex("PUSH 1\nASSERT \"a = b\"").unwrap().get(2));
+    }
+
+    #[test]
+    fn converting_types() {
+        let program = "
+            .var i: i64
+            .var f: f64
+            PUSH -3
+            STORE i
+            f = i + 0.5
+            i = f * 2
+            LOAD f
+            PRINT 2
+            LOAD i
+            PRINT 1
+            HALT
+        ";
+
+        let tokens = Lexer::lex(program).unwrap();
+        let op = |word: &str| Token::OpCode(word.to_string());
+        assert_eq!(
+            vec![op("LOAD"), op("i"), op("ITOF"), op("PUSH"), Token::NumF(0.5), op("ADDF"), op("STORE"), op("f")],
+            tokens[4..12].to_vec()
+        );
+        assert_eq!(vec![op("LOAD"), op("f"), op("PUSH"), Token::NumF(2.0), op("MULF"), op("FTOI")], tokens[12..18].to_vec());
+
+        let (tokens, _) = RegisterAllocator::allocate(tokens).unwrap();
+        let binary = Generator::generate(Parser::parse(tokens).unwrap()).unwrap();
+        assert_eq!("-2.5\n-5\n", run_captured(binary).1);
+
+        //the structured language converts values to the type they are used as instead of rejecting them
+        let program = "
+            let total: f64 = 0.25;
+            let count: u64 = 3;
+            total = total + count;
+            let rounded: i64 = total * -2;
+            print(total);
+            print(rounded);
+        ";
+        let binary = Generator::generate(crate::structured::compile_to_instructions(program).unwrap().to_tokens().0).unwrap();
+        assert_eq!("3.25\n-6\n", run_captured(binary).1);
+    }
+
+    #[test]
+    fn allocating_registers() {
+        //a is finished with before total is written, so they share a register, while register 0 is used by number
+        let program = "
+            .var a
+            .var b
+            .var total
+            PUSH 5
+            STORE 0
+            a = 2
+            total = a * 10
+            b = 3
+            CALL add_b
+            LOAD total
+            PRINT 0
+            LOAD 0
+            PRINT 0
+            HALT
+
+            add_b:
+                total = total + b
+            RETURN
+        ";
+
+        let mut compiler = Compiler::default();
+        compiler.build(program, false).unwrap();
+
+        assert_eq!(
+            vec![("a".to_string(), Location::Register(1)), ("total".to_string(), Location::Register(1)), ("b".to_string(), Location::Register(2))],
+            compiler.get_allocation().variables
+        );
+        assert_eq!("23\n5\n", run_captured(compiler.get_output().clone()).1);
+
+        assert_eq!(
+            Err(CompError::UnexpectedChar("Variable 'count' on line 1 isn't declared with .var".to_string())),
+            Lexer::lex("LOAD count")
+        );
+    }
+
+    #[test]
+    fn spilling_registers() {
+        //all nine values are needed at once when they are added up, so one of them has to go in memory
+        let names: Vec<String> = (0..9).map(|i| format!("v{}", i)).collect();
+        let mut program: String = names.iter().map(|name| format!(".var {}\n", name)).collect();
+        for (i, name) in names.iter().enumerate() {
+            program += &format!("{} = {}\n", name, i + 1);
+        }
+        program += &format!("v0 = {}\nLOAD v0\nPRINT 0\nHALT\n", names.join(" + "));
+
+        let (tokens, allocation) = RegisterAllocator::allocate(Lexer::lex(program.clone()).unwrap()).unwrap();
+        let locations: Vec<Location> = allocation.variables.iter().map(|(_, location)| *location).collect();
+        assert_eq!((0..8).map(Location::Register).chain(Some(Location::Memory(0))).collect::<Vec<_>>(), locations);
+        assert!(tokens.contains(&Token::OpCode("STOREM".to_string())));
+
+        let binary = Generator::generate(Parser::parse(tokens).unwrap()).unwrap();
+        assert_eq!("45\n", run_captured(binary).1);
+
+        //memory address 0 is already used by the program, so the spilled variable goes after it
+        let program = format!("PUSH 7\nSTOREM 0\n{}LOADM 0\nPRINT 0\nHALT\n", program.trim_end_matches("HALT\n"));
+        let (tokens, allocation) = RegisterAllocator::allocate(Lexer::lex(program).unwrap()).unwrap();
+        assert_eq!(Some(&Location::Memory(1)), allocation.variables.last().map(|(_, location)| location));
+
+        let binary = Generator::generate(Parser::parse(tokens).unwrap()).unwrap();
+        assert_eq!("45\n7\n", run_captured(binary).1);
+    }
+
+    #[test]
+    fn listing() {
+        let program = "PUSH 3\nCALL double\nJMP -2\n\ndouble:\n    DUPLI\n    ADDU\nRETURN";
+
+        let path = std::env::temp_dir().join("nariva_listing.binar");
+        let mut compiler = Compiler::new(path.to_string_lossy().into_owned());
+        compiler.set_listing(true);
+        compiler.compile(program, false).unwrap();
+
+        let listing = fs::read_to_string(path.with_extension("lst")).unwrap();
+        assert_eq!(compiler.get_listing(), listing);
+
+        let expected = [
+            "Address Bytes                       Instruction",
+            "        1: PUSH 3",
+            "0011    02 00 00 00 00 00 00 00 03  PUSH 3",
+            "        2: CALL double",
+            "001a    1b 00 00 00 00 00 00 00 2b  CALL 43 -> 002c double",
+            "        3: JMP -2",
+            "0023    16 ff ff ff ff ff ff ff ee  JMP -18 -> 0011",
+            "        double:",
+            "        6: DUPLI",
+            "002c    21                          DUPLI",
+        ];
+        assert_eq!(expected.to_vec(), listing.lines().take(expected.len()).collect::<Vec<_>>());
+        assert!(listing.ends_with("Symbols\n002c    double\n"));
+    }
+
+    #[test]
+    fn compiling_to_memory() {
+        let program = "PUSH 4\nCALL square\nPRINT 0\nHALT\n\nsquare:\n    DUPLI\n    MULU\nRETURN";
+
+        let mut compiler = Compiler::default();
+        let built = compiler.build(program, false).unwrap().clone();
+        assert_eq!("16\n", run_captured(built.bytes.clone()).1);
+        assert_eq!(Some(HEADER.len() + 28), built.symbol("square"));
+        assert_eq!(vec![(17, 1), (26, 2), (35, 3), (44, 4), (45, 7), (46, 8), (47, 9)], built.line_table);
+        assert_eq!(None, built.listing);
+
+        //writing is a separate step, which reports problems instead of panicking
+        assert!(matches!(compiler.write(), Err(CompError::Io(_))));
+
+        let path = std::env::temp_dir().join("nariva_compiling_to_memory.binar");
+        built.write(&path).unwrap();
+        assert_eq!(built.bytes, fs::read(&path).unwrap());
+    }
+
+    #[test]
+    fn disassembling() {
+        let binary = include_bytes!("../../nar files/fizzbuzz.binar").to_vec();
+        let source = Disassembler::disassemble(&binary).unwrap();
+        assert!(source.starts_with("PUSH 20\nSTORE 0\nPUSH 0\nCALL fn@131\n"));
+
+        //the disassembly compiles back into the same program
+        let mut compiler = Compiler::default();
+        assert_eq!(binary, compiler.build(&source, false).unwrap().bytes);
+    }
+
+    #[test]
+    fn invalid_jumps() {
+        //bad jump distances are reported rather than panicking, so a REPL can carry on after them
+        assert!(Parser::parse(Lexer::lex("JMP 0").unwrap()).is_err());
+        assert!(Parser::parse(Lexer::lex("PUSH 1\nJMP 5").unwrap()).is_err());
+    }
+
+    #[test]
+    fn testing_programs() {
+        let program = "PUSH 5\nCALL square\nPRINT 0\nHALT\n\nsquare:\n    DUPLI\n    MULU\n    RETURN\n\n\
+            test \"squares\":\n    PUSH 3\n    CALL square\n    ASSERT_EQ 9\n    PUSH 4\n    CALL square\n    ASSERT_EQ 15\n\n\
+            test \"prints\":\n    PUSH 2\n    CALL square\n    DUPLI\n    STORE 1\n    PRINT 0\n    ASSERT_REG 1 4\n    ASSERT_OUTPUT \"4\\n\"\n\n\
+            test \"fails\":\n    PRINT 0\n";
+
+        let suite = TestSuite::build(program, std::path::Path::new(".")).unwrap();
+        assert_eq!(vec![
+            TestResult { name: "squares".into(), line: 11, failure: Some((17, "ASSERT_EQ expected 15 on the stack but found 16".into())) },
+            TestResult { name: "prints".into(), line: 19, failure: None },
+            TestResult { name: "fails".into(), line: 28, failure: Some((29, "Not enough numbers on the stack at address 124".into())) }
+        ], suite.run(1000));
+
+        assert!(matches!(TestSuite::build("PUSH 1\n", std::path::Path::new(".")), Err(CompError::NoTests(_))));
+
+        //headers without a name are errors rather than panics
+        assert_eq!(Err(CompError::UnexpectedChar("Test on line 2 needs a name between the quotes".into())), TestSuite::build("PUSH 1\ntest \"\":\n    PUSH 2\n", std::path::Path::new(".")));
+        assert!(matches!(TestSuite::build("PUSH 1\ntest \":\n    PUSH 2\n", std::path::Path::new(".")), Err(CompError::NoTests(_))));
+
+        //tests are left out when the program is compiled normally
+        let mut compiler = Compiler::default();
+        assert_eq!("25\n", run_captured(compiler.build(program, false).unwrap().bytes.clone()).1);
+    }
+
+    #[test]
+    fn asserting_and_trapping() {
+        let program = "PUSH 1\nASSERT \"never\"\nCALL check\nHALT\n\ncheck:\n    TRAP 3 \"bad \\\"state\\\"\"\n    RETURN\n";
+        let mut compiler = Compiler::default();
+        let binary = compiler.build(program, false).unwrap().bytes.clone();
+
+        let mut machine = Machine::new();
+        assert!(machine.load(binary.clone()));
+        assert_eq!(Err(VmError::Trap { code: 3, address: 37, message: Some("bad \"state\"".into()) }), machine.resume_with_fuel(100));
+
+        //messages survive disassembling, and being linked from an object at a different address
+        assert_eq!(binary, compiler.build(Disassembler::disassemble(&binary).unwrap(), false).unwrap().bytes);
+        let objects = [ObjectFile::compile("PUSH 1\nHALT").unwrap(), ObjectFile::compile("TRAP 4 \"x\"").unwrap()];
+        assert_eq!(objects[1], ObjectFile::from_bytes(&objects[1].to_bytes()).unwrap());
+        assert_eq!(vec![(27, "x".to_string())], DataSection::split(&Linker::link(&objects).unwrap()).unwrap().1.messages);
+
+        assert!(Parser::parse(Lexer::lex("TRAP 0").unwrap()).is_err());
+        assert!(Parser::parse(Lexer::lex("PUSH 1 \"x\"").unwrap()).is_err());
+
+        let suite = TestSuite::build("test \"zero\":\n    PUSH 0\n    ASSERT \"not zero\"\n", std::path::Path::new(".")).unwrap();
+        assert_eq!(vec![TestResult { name: "zero".into(), line: 1, failure: Some((3, "ASSERT failed: not zero".into())) }], suite.run(1000));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use virtual_machine::data::DataSection;
+use virtual_machine::vm::HEADER;
+
+use crate::{error::CompError, object::{ObjectFile, RelocationKind}};
+
+//combines object files into a single program that the vm can run
+pub struct Linker;
+
+impl Default for Linker {
+    fn default() -> Linker {
+        Linker::new()
+    }
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker
+    }
+
+    /*
+    Places the objects one after the other, in the order given, so the program starts running from the first object.
+    Every export is given its final address, then each relocation is filled in with the address right before the function it calls.
+    Messages of every object are moved along with its code into a single data section at the end.
+    */
+    pub fn link(objects: &[ObjectFile]) -> Result<Vec<u8>, CompError> {
+        let mut bases = Vec::with_capacity(objects.len());
+        let mut symbols: HashMap<&str, (usize, usize)> = HashMap::new();
+        let mut base = HEADER.len();
+
+        for (index, object) in objects.iter().enumerate() {
+            bases.push(base);
+
+            for (name, offset) in &object.exports {
+                if let Some((other, _)) = symbols.insert(name, (index, base + offset)) {
+                    return Err(CompError::UnexpectedChar(format!("Function '{}' is defined in both object {} and object {}", name, other, index)))
+                }
+            }
+
+            base += object.code.len();
+        }
+
+        let mut output = HEADER.to_vec();
+        let mut data = DataSection::new();
+        for (index, object) in objects.iter().enumerate() {
+            let mut code = object.code.clone();
+
+            for relocation in &object.relocations {
+                let operand = match relocation.offset.checked_add(8).and_then(|end| code.get_mut(relocation.offset..end)) {
+                    Some(operand) => operand,
+                    None => return Err(CompError::Impossible(format!("Relocation at {} is outside the code of object {}", relocation.offset, index)))
+                };
+
+                let address = match relocation.kind {
+                    //local calls were compiled as though the object started straight after the header
+                    RelocationKind::Local => {
+                        let mut compiled = [0; 8];
+                        compiled.copy_from_slice(operand);
+                        match u64::from_be_bytes(compiled).checked_sub(HEADER.len() as u64 - 1).and_then(|offset| offset.checked_add(bases[index] as u64 - 1)) {
+                            Some(address) => address,
+                            None => return Err(CompError::UnexpectedChar(format!("Call at {} in object {} goes to an address outside of the object", relocation.offset, index)))
+                        }
+                    },
+                    RelocationKind::Import(import) => {
+                        let name = match object.imports.get(import) {
+                            Some(name) => name,
+                            None => return Err(CompError::Impossible(format!("Object {} has no import {}", index, import)))
+                        };
+
+                        match symbols.get(name.as_str()) {
+                            Some((_, address)) => *address as u64 - 1,
+                            None => return Err(CompError::UnexpectedChar(format!("Function '{}' used by object {} isn't defined in any object", name, index)))
+                        }
+                    }
+                };
+
+                operand.copy_from_slice(&address.to_be_bytes());
+            }
+
+            output.extend_from_slice(&code);
+            data.messages.extend(object.messages.iter().map(|(offset, message)| (bases[index] + offset, message.clone())));
+        }
+
+        output.extend_from_slice(&data.to_bytes());
+        Ok(output)
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Write;
+
+use virtual_machine::data::DataSection;
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompError, generator::Generator, ir::InstructionList, lexer::Lexer, regalloc::Allocation, token::Token};
+
+/*
+Human readable listing of a compiled program, as written to a .lst file.
+Each source line is followed by the instructions it produced, with their address, the bytes emitted for them in hex and where jumps and calls go.
+The function labels and variables, with their addresses and locations, are listed at the end.
+*/
+pub struct Listing;
+
+impl Default for Listing {
+    fn default() -> Listing {
+        Listing::new()
+    }
+}
+
+impl Listing {
+    pub fn new() -> Listing {
+        Listing
+    }
+
+    //tokens are the parsed tokens the binary was generated from, with lines given per token and 0 for lines from included files
+    pub fn generate(tokens: &[Token], lines: &[usize], binary: &[u8], source: &str, allocation: &Allocation) -> Result<String, CompError> {
+        let list = InstructionList::from_tokens(tokens, lines)?;
+        let source: Vec<&str> = source.lines().collect();
+
+        let mut addresses = Vec::with_capacity(list.instructions.len() + 1);
+        let mut address = virtual_machine::vm::HEADER.len();
+        for instruction in &list.instructions {
+            addresses.push(address);
+            address += 1 + instruction.operand.is_some() as usize * 8;
+        }
+        addresses.push(address);
+
+        //messages for ASSERT and TRAP are kept after the code
+        let (end, _) = DataSection::split(binary).map_err(|e| CompError::Impossible(format!("Binary has an unreadable data section: {:?}", e)))?;
+        if end != address {
+            return Err(CompError::Impossible(format!("Binary has {} bytes of code but its instructions end at byte {}", end, address)))
+        }
+
+        let mut output = String::new();
+        writeln!(output, "{:<8}{:<28}Instruction", "Address", "Bytes").unwrap();
+
+        let mut last_line = 0;
+        for (index, instruction) in list.instructions.iter().enumerate() {
+            for label in &instruction.labels {
+                writeln!(output, "{:8}{}:", "", label).unwrap();
+            }
+
+            if instruction.line != 0 && instruction.line != last_line {
+                let text = source.get(instruction.line - 1).map_or("", |text| text.trim());
+                writeln!(output, "{:8}{}: {}", "", instruction.line, text).unwrap();
+                last_line = instruction.line;
+            }
+
+            let bytes: Vec<String> = binary[addresses[index]..addresses[index + 1]].iter().map(|byte| format!("{:02x}", byte)).collect();
+            let mut text = match &instruction.operand {
+                Some(operand) => format!("{} {}", instruction.word, Listing::operand(instruction.opcode, operand)),
+                None => instruction.word.clone()
+            };
+            if let Some(message) = &instruction.message {
+                write!(text, " {}", Lexer::quote(message)).unwrap();
+            }
+
+            if let Some(target) = instruction.target {
+                write!(text, " -> {:04x}", addresses[target]).unwrap();
+                let label = list.instructions.get(target).map_or(list.end_labels.first(), |target| target.labels.first());
+                if let Some(label) = label {
+                    write!(text, " {}", label).unwrap();
+                }
+            }
+
+            writeln!(output, "{:04x}    {:<28}{}", addresses[index], bytes.join(" "), text).unwrap();
+        }
+
+        writeln!(output, "\nSymbols").unwrap();
+        for (token, address) in tokens.iter().zip(Generator::addresses(tokens)) {
+            if let Token::Func(name) = token {
+                writeln!(output, "{:04x}    {}", address, name).unwrap();
+            }
+        }
+
+        if !allocation.variables.is_empty() {
+            write!(output, "\n{}", allocation).unwrap();
+        }
+
+        Ok(output)
+    }
+
+    //jump distances are shown as signed numbers, so jumps backwards read as negative
+    fn operand(opcode: OpCode, operand: &Token) -> String {
+        match operand {
+            Token::NumU(num) if matches!(opcode, OpCode::JMP) || opcode.is_conditional_jump() => i64::from_be_bytes(num.to_be_bytes()).to_string(),
+            Token::NumU(num) => num.to_string(),
+            Token::NumI(num) => num.to_string(),
+            Token::NumF(num) => num.to_string(),
+            Token::OpCode(word) | Token::Func(word) => word.clone(),
+            Token::Text(text) => Lexer::quote(text)
+        }
+    }
+}
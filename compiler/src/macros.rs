@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompError, token::Token};
+
+//macros can use other macros, but only this many deep, which stops a macro from expanding into itself forever
+const MAX_DEPTH: usize = 32;
+
+//a block of tokens defined with .macro NAME params ... .endm
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+
+    //tokens in the macro and the line each one was written on
+    pub body: Vec<(Token, usize)>,
+    pub line: usize
+}
+
+//replaces every use of a macro with its body
+pub struct MacroExpander;
+
+impl Default for MacroExpander {
+    fn default() -> MacroExpander {
+        MacroExpander::new()
+    }
+}
+
+impl MacroExpander {
+    pub fn new() -> MacroExpander {
+        MacroExpander
+    }
+
+    /*
+    Takes tokens along with the line of each, removes macro definitions and expands every use of a macro.
+    Parameters and arguments are the tokens written on the same line as the macro's name.
+    Expanded tokens are given the line the macro was used on.
+    */
+    pub fn expand(tokens: Vec<Token>, lines: Vec<usize>) -> Result<(Vec<Token>, Vec<usize>), CompError> {
+        let mut macros: HashMap<String, Macro> = HashMap::new();
+        let mut rest = Vec::new();
+        let mut tokens = tokens.into_iter().zip(lines).peekable();
+
+        while let Some((token, line)) = tokens.next() {
+            match &token {
+                Token::OpCode(word) if word == ".macro" => {
+                    let mac = MacroExpander::definition(&mut tokens, line)?;
+                    if let Some(existing) = macros.get(&mac.name) {
+                        return Err(CompError::UnexpectedChar(format!("Macro '{}' on line {} is already defined on line {}", mac.name, line, existing.line)))
+                    }
+                    macros.insert(mac.name.clone(), mac);
+                },
+                Token::OpCode(word) if word == ".endm" => return Err(CompError::UnexpectedChar(format!("'.endm' on line {} doesn't close a macro", line))),
+                _ => rest.push((token, line))
+            }
+        }
+
+        if macros.is_empty() {
+            return Ok(rest.into_iter().unzip())
+        }
+
+        let mut expansions = 0;
+        Ok(MacroExpander::expand_tokens(rest, &macros, &mut expansions, 0)?.into_iter().unzip())
+    }
+
+    //reads the rest of a macro definition, after .macro
+    fn definition<I: Iterator<Item = (Token, usize)>>(tokens: &mut std::iter::Peekable<I>, line: usize) -> Result<Macro, CompError> {
+        let name = match tokens.next() {
+            Some((Token::OpCode(name), name_line)) if name_line == line => name,
+            _ => return Err(CompError::UnexpectedChar(format!("Macro on line {} needs a name", line)))
+        };
+
+        let mut params = Vec::new();
+        while let Some((_, param_line)) = tokens.peek() {
+            if *param_line != line {
+                break
+            }
+
+            match tokens.next() {
+                Some((Token::OpCode(param), _)) => params.push(param),
+                _ => return Err(CompError::UnexpectedChar(format!("Parameters of macro '{}' on line {} must be words", name, line)))
+            }
+        }
+
+        let mut body = Vec::new();
+        loop {
+            match tokens.next() {
+                Some((Token::OpCode(word), _)) if word == ".endm" => break,
+                Some((Token::OpCode(word), inner_line)) if word == ".macro" => {
+                    return Err(CompError::UnexpectedChar(format!("Macro on line {} is defined inside macro '{}' on line {}", inner_line, name, line)))
+                },
+                Some(token) => body.push(token),
+                None => return Err(CompError::UnexpectedEOF(format!("Macro '{}' on line {} is missing '.endm'", name, line)))
+            }
+        }
+
+        Ok(Macro { name, params, body, line })
+    }
+
+    fn expand_tokens(tokens: Vec<(Token, usize)>, macros: &HashMap<String, Macro>, expansions: &mut usize, depth: usize) -> Result<Vec<(Token, usize)>, CompError> {
+        let mut output = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+
+        while let Some((token, line)) = tokens.next() {
+            let mac = match &token {
+                Token::OpCode(word) => match macros.get(word) {
+                    Some(mac) => mac,
+                    None => {
+                        output.push((token, line));
+                        continue
+                    }
+                },
+                _ => {
+                    output.push((token, line));
+                    continue
+                }
+            };
+
+            let mut args = Vec::new();
+            while args.len() < mac.params.len() && tokens.peek().is_some_and(|(_, arg_line)| *arg_line == line) {
+                args.push(tokens.next().unwrap().0);
+            }
+
+            if args.len() != mac.params.len() || tokens.peek().is_some_and(|(_, arg_line)| *arg_line == line) {
+                return Err(CompError::UnexpectedChar(format!("Macro '{}' defined on line {} takes {} argument(s), used on line {}", mac.name, mac.line, mac.params.len(), line)))
+            }
+
+            if depth >= MAX_DEPTH {
+                return Err(CompError::Overflow(format!("Macro '{}' defined on line {} expands into itself, used on line {}", mac.name, mac.line, line)))
+            }
+
+            let body = MacroExpander::substitute(mac, &args, macros, *expansions, line)?;
+            *expansions += 1;
+
+            let expanded = MacroExpander::expand_tokens(body, macros, expansions, depth + 1)?;
+            output.extend(expanded.into_iter().map(|(token, _)| (token, line)));
+        }
+
+        Ok(output)
+    }
+
+    /*
+    Gives a copy of the macro's body with parameters replaced by arguments.
+    Labels defined in the macro are made unique to this expansion, so a macro can be used more than once.
+    */
+    fn substitute(mac: &Macro, args: &[Token], macros: &HashMap<String, Macro>, expansion: usize, call_line: usize) -> Result<Vec<(Token, usize)>, CompError> {
+        let locals: Vec<&String> = mac.body.iter().filter_map(|(token, _)| match token {
+            Token::Func(name) => Some(name),
+            _ => None
+        }).collect();
+        let local_name = |name: &String| format!("{}.{}", name, expansion);
+
+        let mut body: Vec<(Token, usize)> = Vec::with_capacity(mac.body.len());
+        for (token, line) in &mac.body {
+            let token = match token {
+                Token::OpCode(word) => match mac.params.iter().position(|param| param == word) {
+                    Some(param) => args[param].clone(),
+                    None if locals.contains(&word) => Token::OpCode(local_name(word)),
+                    None => token.clone()
+                },
+                Token::Func(name) => Token::Func(local_name(name)),
+                _ => token.clone()
+            };
+
+            //words must be opcodes, function names after a CALL, or other macros, which are checked when they expand
+            if let Token::OpCode(word) = &token {
+                let after_call = body.last().is_some_and(|(previous, _)| previous.is_call());
+                if OpCode::from(word) == OpCode::Illegal && !after_call && !macros.contains_key(word) {
+                    return Err(CompError::UnexpectedChar(format!("'{}' opcode doesn't exist, in macro '{}' defined on line {} (line {}), used on line {}", word, mac.name, mac.line, line, call_line)))
+                }
+            }
+
+            body.push((token, *line));
+        }
+
+        Ok(body)
+    }
+}
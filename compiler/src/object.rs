@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+
+use virtual_machine::data::DataSection;
+use virtual_machine::vm::HEADER;
+
+use crate::{error::CompError, generator::Generator, lexer::Lexer, parser::Parser, regalloc::RegisterAllocator, token::Token};
+
+pub const OBJECT_HEADER: &[u8] = b"Nariva Object";
+pub const OBJECT_VERSION: u8 = 2;
+
+//what the 8 bytes at a relocation should be replaced with when objects are linked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationKind {
+    //a CALL to a function in the same object, which moves with the object
+    Local,
+
+    //a CALL to the function at this index in the object's imports
+    Import(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relocation {
+    //position in the object's code of the 8 byte operand
+    pub offset: usize,
+    pub kind: RelocationKind
+}
+
+/*
+A compiled program which hasn't been given its final place in a .binar file.
+Its code is compiled as though it starts straight after the header, and relocations mark every CALL operand that changes when it is placed somewhere else.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectFile {
+    //compiled code, without the header
+    pub code: Vec<u8>,
+
+    //functions defined in the object and their position in its code
+    pub exports: Vec<(String, usize)>,
+
+    //functions called by the object that other objects must define
+    pub imports: Vec<String>,
+    pub relocations: Vec<Relocation>,
+
+    //messages for ASSERT and TRAP instructions, by the position of the instruction in the code
+    pub messages: Vec<(usize, String)>
+}
+
+impl ObjectFile {
+    pub fn compile<S: Into<String>>(input: S) -> Result<ObjectFile, CompError> {
+        ObjectFile::from_tokens(Lexer::lex(input)?)
+    }
+
+    //Compiles lexed tokens, with calls to functions that aren't defined becoming imports
+    pub fn from_tokens(mut tokens: Vec<Token>) -> Result<ObjectFile, CompError> {
+        let defined: HashSet<String> = tokens.iter().filter_map(|token| match token {
+            Token::Func(name) => Some(name.clone()),
+            _ => None
+        }).collect();
+
+        //name of the function called by each CALL, found before the parser replaces them with addresses
+        let calls: Vec<(usize, String)> = tokens.windows(2).enumerate().filter_map(|(index, pair)| match &pair[1] {
+            Token::OpCode(name) if pair[0].is_call() => Some((index + 1, name.clone())),
+            _ => None
+        }).collect();
+
+        let mut imports: Vec<String> = Vec::new();
+        for (_, name) in &calls {
+            if !defined.contains(name) && !imports.contains(name) {
+                imports.push(name.clone());
+            }
+        }
+
+        //imports are given labels at the end so the parser accepts them, and are replaced when linking
+        let code_tokens = tokens.len();
+        tokens.extend(imports.iter().map(|name| Token::Func(name.clone())));
+
+        //variables are given registers within this object only
+        let (tokens, _) = RegisterAllocator::allocate(tokens)?;
+        let mut tokens = Parser::parse(tokens)?;
+        tokens.truncate(code_tokens);
+        let addresses = Generator::addresses(&tokens);
+
+        let exports = tokens.iter().zip(addresses.iter()).filter_map(|(token, address)| match token {
+            Token::Func(name) => Some((name.clone(), address - HEADER.len())),
+            _ => None
+        }).collect();
+
+        let relocations = calls.iter().map(|(index, name)| Relocation {
+            offset: addresses[*index] - HEADER.len(),
+            kind: match imports.iter().position(|import| import == name) {
+                Some(import) => RelocationKind::Import(import),
+                None => RelocationKind::Local
+            }
+        }).collect();
+
+        //the data section is split off and linked separately, as its addresses move with the object
+        let mut code = Generator::generate(tokens)?;
+        let (end, data) = DataSection::split(&code).map_err(|e| CompError::Impossible(format!("Generated code has an unreadable data section: {:?}", e)))?;
+        code.truncate(end);
+        let code = code[HEADER.len()..].to_vec();
+        let messages = data.messages.into_iter().map(|(address, message)| (address - HEADER.len(), message)).collect();
+
+        Ok(ObjectFile { code, exports, imports, relocations, messages })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = OBJECT_HEADER.to_vec();
+        bytes.push(OBJECT_VERSION);
+
+        bytes.extend_from_slice(&(self.code.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.code);
+
+        bytes.extend_from_slice(&(self.exports.len() as u64).to_be_bytes());
+        for (name, offset) in &self.exports {
+            ObjectFile::push_name(&mut bytes, name);
+            bytes.extend_from_slice(&(*offset as u64).to_be_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.imports.len() as u64).to_be_bytes());
+        for name in &self.imports {
+            ObjectFile::push_name(&mut bytes, name);
+        }
+
+        bytes.extend_from_slice(&(self.relocations.len() as u64).to_be_bytes());
+        for relocation in &self.relocations {
+            bytes.extend_from_slice(&(relocation.offset as u64).to_be_bytes());
+            match relocation.kind {
+                RelocationKind::Local => bytes.push(0),
+                RelocationKind::Import(import) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(import as u64).to_be_bytes());
+                }
+            }
+        }
+
+        bytes.extend_from_slice(&(self.messages.len() as u64).to_be_bytes());
+        for (offset, message) in &self.messages {
+            bytes.extend_from_slice(&(*offset as u64).to_be_bytes());
+            ObjectFile::push_name(&mut bytes, message);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjectFile, CompError> {
+        if bytes.len() <= OBJECT_HEADER.len() || &bytes[..OBJECT_HEADER.len()] != OBJECT_HEADER {
+            return Err(CompError::UnexpectedChar("Data is not a nariva object".into()))
+        }
+
+        if bytes[OBJECT_HEADER.len()] != OBJECT_VERSION {
+            return Err(CompError::UnexpectedChar(format!("Unsupported object version {}", bytes[OBJECT_HEADER.len()])))
+        }
+
+        let mut reader = ObjectReader { bytes, index: OBJECT_HEADER.len() + 1 };
+
+        let code_len = reader.take_count()?;
+        let code = reader.take(code_len)?.to_vec();
+
+        let mut exports = Vec::new();
+        for _ in 0..reader.take_count()? {
+            exports.push((reader.take_name()?, reader.take_count()?));
+        }
+
+        let mut imports = Vec::new();
+        for _ in 0..reader.take_count()? {
+            imports.push(reader.take_name()?);
+        }
+
+        let mut relocations = Vec::new();
+        for _ in 0..reader.take_count()? {
+            let offset = reader.take_count()?;
+            let kind = match reader.take(1)?[0] {
+                0 => RelocationKind::Local,
+                1 => RelocationKind::Import(reader.take_count()?),
+                kind => return Err(CompError::UnexpectedChar(format!("Unknown relocation kind {}", kind)))
+            };
+            relocations.push(Relocation { offset, kind });
+        }
+
+        let mut messages = Vec::new();
+        for _ in 0..reader.take_count()? {
+            messages.push((reader.take_count()?, reader.take_name()?));
+        }
+
+        if reader.index != bytes.len() {
+            return Err(CompError::UnexpectedChar("Object has data after its messages".into()))
+        }
+
+        let object = ObjectFile { code, exports, imports, relocations, messages };
+        object.check()?;
+        Ok(object)
+    }
+
+    //makes sure everything read from an object points inside its code, so linking it can't go wrong
+    fn check(&self) -> Result<(), CompError> {
+        let outside = |what: String| Err(CompError::UnexpectedChar(format!("Object has {} outside of its code", what)));
+
+        for (name, offset) in &self.exports {
+            if *offset > self.code.len() {
+                return outside(format!("function '{}' at {}", name, offset))
+            }
+        }
+
+        for (offset, _) in &self.messages {
+            if *offset >= self.code.len() {
+                return outside(format!("a message at {}", offset))
+            }
+        }
+
+        for relocation in &self.relocations {
+            let operand = match relocation.offset.checked_add(8).and_then(|end| self.code.get(relocation.offset..end)) {
+                Some(operand) => operand,
+                None => return outside(format!("a relocation at {}", relocation.offset))
+            };
+
+            match relocation.kind {
+                //local calls hold the address right before the function, as though the object started straight after the header
+                RelocationKind::Local => {
+                    let mut compiled = [0; 8];
+                    compiled.copy_from_slice(operand);
+                    let target = u64::from_be_bytes(compiled).checked_sub(HEADER.len() as u64 - 1);
+                    if target.is_none_or(|target| target > self.code.len() as u64) {
+                        return outside(format!("a call at {}", relocation.offset))
+                    }
+                },
+                RelocationKind::Import(import) if import >= self.imports.len() => {
+                    return Err(CompError::UnexpectedChar(format!("Object has a relocation at {} to import {}, but only {} imports", relocation.offset, import, self.imports.len())))
+                },
+                RelocationKind::Import(_) => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_name(bytes: &mut Vec<u8>, name: &str) {
+        bytes.extend_from_slice(&(name.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+}
+
+struct ObjectReader<'a> {
+    bytes: &'a [u8],
+    index: usize
+}
+
+impl<'a> ObjectReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CompError> {
+        match self.bytes.get(self.index..self.index.saturating_add(len)) {
+            Some(taken) => {
+                self.index += len;
+                Ok(taken)
+            },
+            None => Err(CompError::UnexpectedEOF("Object ended unexpectedly".into()))
+        }
+    }
+
+    fn take_count(&mut self) -> Result<usize, CompError> {
+        let bytes = self.take(8)?;
+        let mut num = [0; 8];
+        num.copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(num) as usize)
+    }
+
+    fn take_name(&mut self) -> Result<String, CompError> {
+        let len = self.take_count()?;
+        match String::from_utf8(self.take(len)?.to_vec()) {
+            Ok(name) => Ok(name),
+            Err(_) => Err(CompError::UnexpectedChar("Object has a name that isn't valid text".into()))
+        }
+    }
+}
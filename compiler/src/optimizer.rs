@@ -0,0 +1,131 @@
+use virtual_machine::instruction::OpCode;
+
+use crate::{cfg::ControlFlowGraph, error::CompError, folder::Folder, ir::{Instruction, InstructionList}, token::Token};
+
+//optional pass between the parser and the generator that removes instructions which do nothing
+pub struct Optimizer;
+
+impl Default for Optimizer {
+    fn default() -> Optimizer {
+        Optimizer::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Optimizer {
+        Optimizer
+    }
+
+    //takes parsed tokens and the line of each token, returning the optimized tokens and their lines
+    pub fn optimize(tokens: Vec<Token>, lines: Vec<usize>) -> Result<(Vec<Token>, Vec<usize>), CompError> {
+        let mut list = InstructionList::from_tokens(&tokens, &lines)?;
+
+        //removing one pattern can line up another, e.g. PUSH 1 PUSH 2 POP POP
+        loop {
+            let stripped = ControlFlowGraph::new(&list).strip(&mut list);
+            let folded = Folder::fold(&mut list);
+            let removed = Optimizer::peephole(&mut list);
+
+            if !stripped && !folded && !removed {
+                break
+            }
+        }
+
+        Ok(list.to_tokens())
+    }
+
+    //runs every pattern once over the program, returning whether anything changed
+    pub fn peephole(list: &mut InstructionList) -> bool {
+        let len = list.instructions.len();
+        let mut removed = vec![false; len];
+        let mut changed = false;
+        let mut index = 0;
+
+        while index < len {
+            let current = &list.instructions[index];
+
+            //a jump to the very next instruction does the same as not jumping
+            if current.target == Some(index + 1) && current.opcode != OpCode::Call {
+                removed[index] = true;
+                changed = true;
+                index += 1;
+                continue
+            }
+
+            //the second instruction of a pattern must only be reachable from the first
+            if index + 1 >= len || list.is_entry_point(index + 1) {
+                index += 1;
+                continue
+            }
+
+            /*
+            Patterns that use a value already on the stack would fault with an empty stack, so they are only removed when the instruction
+            before them, which always runs first, leaves that value there. Otherwise the fault is kept.
+            */
+            let has_value = index > 0 && !removed[index - 1] && !list.is_entry_point(index) && Optimizer::leaves_value(list.instructions[index - 1].opcode);
+
+            let next = &list.instructions[index + 1];
+            match (current.opcode, next.opcode) {
+                //values that are pushed and immediately thrown away
+                (OpCode::Push, OpCode::Pop) => {
+                    removed[index] = true;
+                    removed[index + 1] = true;
+                },
+                (OpCode::Dupli, OpCode::Pop) if has_value => {
+                    removed[index] = true;
+                    removed[index + 1] = true;
+                },
+
+                //adding or taking away 0, or multiplying or dividing by 1, leaves the value as it is
+                (OpCode::Push, OpCode::AddU) | (OpCode::Push, OpCode::SubU) if has_value && current.operand == Some(Token::NumU(0)) => {
+                    removed[index] = true;
+                    removed[index + 1] = true;
+                },
+                (OpCode::Push, OpCode::MulU) | (OpCode::Push, OpCode::DivU) if has_value && current.operand == Some(Token::NumU(1)) => {
+                    removed[index] = true;
+                    removed[index + 1] = true;
+                },
+
+                //storing a value and loading it straight back is the same as copying it before storing
+                (OpCode::Store, OpCode::Load) if current.operand == next.operand => {
+                    let store = current.clone();
+                    list.instructions[index] = Instruction {
+                        opcode: OpCode::Dupli,
+                        word: "DUPLI".to_string(),
+                        operand: None,
+                        target: None,
+                        labels: store.labels,
+                        line: store.line,
+                        message: None
+                    };
+                    list.instructions[index + 1] = Instruction { labels: Vec::new(), ..store };
+                },
+
+                _ => {
+                    index += 1;
+                    continue
+                }
+            }
+
+            changed = true;
+            index += 2;
+        }
+
+        if removed.contains(&true) {
+            list.remove(&removed);
+        }
+
+        changed
+    }
+
+    //whether an instruction that runs without faulting always leaves at least one value on the stack
+    fn leaves_value(opcode: OpCode) -> bool {
+        matches!(opcode,
+            OpCode::Push | OpCode::Dupli | OpCode::Load | OpCode::LoadM |
+            OpCode::AddU | OpCode::SubU | OpCode::MulU | OpCode::DivU | OpCode::ModU |
+            OpCode::AddI | OpCode::SubI | OpCode::MulI | OpCode::DivI | OpCode::ModI |
+            OpCode::AddF | OpCode::SubF | OpCode::MulF | OpCode::DivF | OpCode::ModF |
+            OpCode::Shift | OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor | OpCode::BitNot |
+            OpCode::UtoF | OpCode::ItoF | OpCode::FtoU | OpCode::FtoI)
+    }
+}
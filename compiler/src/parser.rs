@@ -1,5 +1,4 @@
-use core::num;
-use std::{fmt::{Error, format}, collections::HashMap, convert::TryInto, thread::current};
+use std::collections::HashMap;
 
 use virtual_machine::{vm::HEADER, instruction::OpCode};
 
@@ -8,14 +7,25 @@ use crate::{error::CompError, token::Token};
 //ensures that tokens are arranged in a certain order / obey certain rules
 pub struct Parser;
 
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
 impl Parser {
     pub fn new() -> Parser {
         Parser
     }
 
-    pub fn parse(mut program: Vec<Token>) -> Result<Vec<Token>, CompError> {
+    pub fn parse(program: Vec<Token>) -> Result<Vec<Token>, CompError> {
+        Parser::parse_located(program).map_err(|(_, e)| e)
+    }
+
+    //same as parse, with errors also giving the index of the token they were found at, so they can be traced back to a file and line
+    pub fn parse_located(mut program: Vec<Token>) -> Result<Vec<Token>, (usize, CompError)> {
         if program.is_empty() {
-            return Err(CompError::UnexpectedEOF("Input program is empty".into()));
+            return Err((0, CompError::UnexpectedEOF("Input program is empty".into())));
         }
 
         let mut function_record: HashMap<String, usize> = HashMap::new();
@@ -30,9 +40,17 @@ impl Parser {
             }
 
             match &program[program_index] {
+                //labels are kept in the output but take up no space in the binary
                 Token::Func(name) => {
                     function_record.insert(name.clone(), binary_index);
-                    program.remove(program_index);
+                    program_index += 1;
+                    continue
+                },
+
+                //messages are kept in the data section, after the code
+                Token::Text(_) => {
+                    program_index += 1;
+                    continue
                 },
 
                 Token::NumU(_) | Token::NumI(_) | Token::NumF(_) => {
@@ -44,9 +62,6 @@ impl Parser {
                         binary_index += 7
                     }
                 },
-
-
-                _ => ()
             };
 
             program_index += 1;
@@ -55,6 +70,14 @@ impl Parser {
 
         //panic!(format!("{:?}", function_record));
 
+        let mut at = 0;
+        Parser::check(&mut program, &function_record, &mut at).map_err(|e| (at, e))?;
+
+        Ok(program)
+    }
+
+    //checks every token in turn, leaving at on the token being checked when an error is found
+    fn check(program: &mut [Token], function_record: &HashMap<String, usize>, at: &mut usize) -> Result<(), CompError> {
         let mut index = 0;
         //general loop
         loop {
@@ -63,10 +86,11 @@ impl Parser {
                 break;
             }
 
+            *at = index;
             match &program[index]{
                 Token::OpCode(word) => {
                     match word.as_str() {
-                        "Push" | "PUSH" | "CMP" | "PRINT" | "Print" | "Load" | "LOAD" | "Store" | "STORE" | "PrintSTR" | "PRINTSTR"=> {
+                        "Push" | "PUSH" | "Shift" | "SHIFT" | "CMP" | "PRINT" | "Print" | "Load" | "LOAD" | "Store" | "STORE" | "PrintSTR" | "PRINTSTR" | "LoadM" | "LOADM" | "StoreM" | "STOREM" => {
 
                             if program.len() > index + 1 && program[index+1].is_num()  {
                                 index += 2
@@ -83,11 +107,11 @@ impl Parser {
                                 match program[index + 1] {
                                     Token::NumU(num) => {
                                         let num_clone = i64::from_be_bytes(num.to_be_bytes());
-                                        program[index + 1] = Token::NumU(Parser::get_jump_index(num_clone, &program, index).unwrap())
+                                        program[index + 1] = Token::NumU(Parser::get_jump_index(num_clone, program, index)?)
                                     },
                                     Token::NumI(num) => {
                                         let num_clone = i64::from_be_bytes(num.to_be_bytes());
-                                        program[index + 1] = Token::NumU(Parser::get_jump_index(num_clone, &program, index).unwrap())
+                                        program[index + 1] = Token::NumU(Parser::get_jump_index(num_clone, program, index)?)
                                     }
                                     _ => return Err(CompError::UnexpectedChar(format!("unsigned Number needed after a '{}' opcode", word)))
 
@@ -101,7 +125,15 @@ impl Parser {
                             }
                         },
 
-                        
+                        //code 0 is kept for failed ASSERTs, and either can be followed by a message
+                        "Trap" | "TRAP" => match program.get(index + 1) {
+                            Some(Token::NumU(0)) => return Err(CompError::UnexpectedChar(format!("'{}' code can't be 0, which is used by failed ASSERTs", word))),
+                            Some(Token::NumU(_)) => index += 2 + Parser::message_len(program, index + 2),
+                            _ => return Err(CompError::UnexpectedChar(format!("Whole number needed after a '{}' opcode", word)))
+                        },
+
+                        "Assert" | "ASSERT" => index += 1 + Parser::message_len(program, index + 1),
+
                         "Call" | "CALL" => {
                             if program.len() > index + 1 {
                                 index += 1;
@@ -137,18 +169,24 @@ impl Parser {
                     }
                 },
 
-                Token::Func(_) => unreachable!(),
+                Token::Func(_) => index += 1,
 
-                Token::NumU(_) | Token::NumI(_) | Token::NumF(_) => return Err(CompError::UnexpectedChar("Numbers must only proceed words".to_string()))
+                Token::NumU(_) | Token::NumI(_) | Token::NumF(_) => return Err(CompError::UnexpectedChar("Numbers must only proceed words".to_string())),
+
+                Token::Text(text) => return Err(CompError::UnexpectedChar(format!("Text \"{}\" can only be given to ASSERT or TRAP", text)))
 
             }
         }
 
-        return Ok(program)
+        Ok(())
+    }
 
+    //1 if the token at index is a message, which is skipped over
+    fn message_len(program: &[Token], index: usize) -> usize {
+        matches!(program.get(index), Some(Token::Text(_))) as usize
     }
 
-    fn get_jump_index(desired_num_opcodes: i64, program: &Vec<Token>, mut current_index: usize) -> Result<u64, CompError> {
+    fn get_jump_index(desired_num_opcodes: i64, program: &[Token], mut current_index: usize) -> Result<u64, CompError> {
         let forward_jump = desired_num_opcodes > 0;
         
         let desired_num_opcodes = desired_num_opcodes.abs();
@@ -168,35 +206,34 @@ impl Parser {
 
             if forward_jump {
                 current_index += 1;
-                binary_index += 1;
+            }
+            else if current_index == 0 {
+                return Err(CompError::UnexpectedEOF("Jump to distance too far back".to_string()))
             }
             else {
-                
                 current_index -= 1;
-                binary_index -= 1;
             }
 
             if current_index >= program.len() {
                 return Err(CompError::UnexpectedEOF("Jump to distance greater than file".to_string()))
             }
-            /*
-            Not possible
-            else if current_index < 0 {
-                return Err(CompError::UnexpectedEOF("Jump to distance too far back".to_string()))
-            }
-            */
 
-            match program[current_index] {
-                Token::OpCode(_) => current_num_opcodes += 1,
-                Token::NumF(_) | Token::NumI(_) | Token::NumU(_) => {
-                    if forward_jump {
-                        binary_index += 7;
-                    }
-                    else {
-                        binary_index -= 7;
-                    }
+            let size = match program[current_index] {
+                //the name of the function after a CALL is replaced by its address
+                Token::OpCode(_) if current_index > 0 && program[current_index - 1].is_call() => 8,
+                Token::OpCode(_) => {
+                    current_num_opcodes += 1;
+                    1
                 },
-                _ => continue
+                Token::NumF(_) | Token::NumI(_) | Token::NumU(_) => 8,
+                Token::Func(_) | Token::Text(_) => 0
+            };
+
+            if forward_jump {
+                binary_index += size;
+            }
+            else {
+                binary_index -= size;
             }
 
             //println!("Current I: {:?}, Bin: {}, PrADD: {}", program[current_index], binary_index, current_index);
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{error::{CompError, CompWarning}, regalloc::Allocation};
+
+/*
+A compiled program held in memory, along with what is known about it for debugging.
+Nothing is written to disk until write is called, so programs can be compiled without a filesystem.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    //the binary that the virtual machine runs, starting with its header
+    pub bytes: Vec<u8>,
+
+    //address in the binary of every function label
+    pub symbols: Vec<(String, usize)>,
+
+    //address of each instruction paired with the source line it came from
+    pub line_table: Vec<(usize, usize)>,
+
+    //where each variable declared with .var is kept
+    pub allocation: Allocation,
+
+    //problems found while compiling which did not stop it
+    pub warnings: Vec<CompWarning>,
+
+    //listing of the program, when the compiler was asked for one
+    pub listing: Option<String>,
+
+    //files the program was compiled from, starting with the program itself, so tools can tell when it needs compiling again
+    pub files: Vec<PathBuf>
+}
+
+impl Program {
+    //writes the binary to path, along with the listing as a .lst file next to it if there is one
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), CompError> {
+        let path = path.as_ref();
+        fs::write(path, &self.bytes).map_err(|e| CompError::Io(format!("Cannot write '{}': {}", path.display(), e)))?;
+
+        if let Some(listing) = &self.listing {
+            let path = path.with_extension("lst");
+            fs::write(&path, listing).map_err(|e| CompError::Io(format!("Cannot write '{}': {}", path.display(), e)))?;
+        }
+
+        Ok(())
+    }
+
+    //address of the function with the given label
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().find(|(symbol, _)| symbol == name).map(|(_, address)| *address)
+    }
+}
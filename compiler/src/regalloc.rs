@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{cfg::ControlFlowGraph, error::CompError, ir::InstructionList, parser::Parser, token::Token};
+
+const REGISTER_COUNT: usize = 8;
+
+//where a variable is kept while the program runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    Register(usize),
+
+    //address in the vm's memory, used once the registers are full
+    Memory(usize)
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Register(register) => write!(f, "register {}", register),
+            Location::Memory(address) => write!(f, "memory {}", address)
+        }
+    }
+}
+
+//location given to each variable, in the order they are first used
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Allocation {
+    pub variables: Vec<(String, Location)>
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.variables.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("Variable".len());
+
+        writeln!(f, "{:width$}  Location", "Variable", width = width)?;
+        for (name, location) in &self.variables {
+            writeln!(f, "{:width$}  {}", name, location, width = width)?;
+        }
+
+        Ok(())
+    }
+}
+
+/*
+Gives each variable declared with .var a register, using liveness analysis so that variables which are never needed at the same time can share one.
+Registers that the program uses by number are left alone, and variables that don't fit in the rest are moved to memory with STOREM and LOADM,
+at addresses the program doesn't already use by number.
+Calls are followed into functions and back out of every RETURN to the instruction after each call, so variables used on either side of a call
+are never given a register that the function writes to.
+*/
+pub struct RegisterAllocator;
+
+impl Default for RegisterAllocator {
+    fn default() -> RegisterAllocator {
+        RegisterAllocator::new()
+    }
+}
+
+impl RegisterAllocator {
+    pub fn new() -> RegisterAllocator {
+        RegisterAllocator
+    }
+
+    //replaces variable names after LOAD and STORE in lexed tokens with the registers or memory addresses they are given
+    pub fn allocate(tokens: Vec<Token>) -> Result<(Vec<Token>, Allocation), CompError> {
+        RegisterAllocator::allocate_files(tokens, &[], |_, name| name.to_string())
+    }
+
+    /*
+    Same as allocate for tokens lexed from several files, where files holds the index of the file each token came from.
+    Variables belong to the file they are declared in, so two files can each have their own variable with the same name,
+    and label gives the name each variable is shown with in the allocation.
+    */
+    pub fn allocate_files<F: Fn(usize, &str) -> String>(mut tokens: Vec<Token>, files: &[usize], label: F) -> Result<(Vec<Token>, Allocation), CompError> {
+        let file = |index: usize| files.get(index).copied().unwrap_or(0);
+        let mut names: Vec<(usize, String)> = Vec::new();
+
+        //variable, if any, used by each LOAD and STORE in order, along with registers and memory addresses that are used by number
+        let mut accesses: Vec<Option<usize>> = Vec::new();
+        let mut reserved: HashSet<usize> = HashSet::new();
+        let mut reserved_memory: HashSet<usize> = HashSet::new();
+
+        for index in 0..tokens.len() {
+            if RegisterAllocator::is_memory_access(&tokens[index]) {
+                if let Some(Token::NumU(address)) = tokens.get(index + 1) {
+                    reserved_memory.insert(*address as usize);
+                }
+                continue
+            }
+
+            if !RegisterAllocator::is_access(&tokens[index]) {
+                continue
+            }
+
+            match tokens.get(index + 1) {
+                Some(Token::OpCode(name)) => {
+                    let key = (file(index + 1), name.clone());
+                    let variable = match names.iter().position(|known| *known == key) {
+                        Some(variable) => variable,
+                        None => {
+                            names.push(key);
+                            names.len() - 1
+                        }
+                    };
+                    accesses.push(Some(variable));
+                },
+                Some(Token::NumU(register)) => {
+                    reserved.insert(*register as usize);
+                    accesses.push(None);
+                },
+                _ => accesses.push(None)
+            }
+        }
+
+        if names.is_empty() {
+            return Ok((tokens, Allocation::default()))
+        }
+
+        //the program is parsed with placeholders for variables to find where its jumps and calls go
+        let placeholders: Vec<Token> = tokens.iter().enumerate().map(|(index, token)| match token {
+            Token::OpCode(_) if index > 0 && RegisterAllocator::is_access(&tokens[index - 1]) => Token::NumU(0),
+            token => token.clone()
+        }).collect();
+
+        let parsed = Parser::parse(placeholders)?;
+        let list = InstructionList::from_tokens(&parsed, &vec![0; parsed.len()])?;
+
+        let mut instruction_accesses = accesses.into_iter();
+        let variables: Vec<Option<usize>> = list.instructions.iter().map(|instruction| match instruction.opcode {
+            OpCode::Load | OpCode::Store => instruction_accesses.next().flatten(),
+            _ => None
+        }).collect();
+
+        let interference = RegisterAllocator::interference(&list, &variables, names.len());
+        let locations = RegisterAllocator::colour(&interference, &reserved, &reserved_memory);
+
+        for index in 1..tokens.len() {
+            if !RegisterAllocator::is_access(&tokens[index - 1]) {
+                continue
+            }
+
+            let name = match &tokens[index] {
+                Token::OpCode(name) => name.clone(),
+                _ => continue
+            };
+
+            let key = (file(index), name);
+            let variable = names.iter().position(|known| *known == key).unwrap();
+            match locations[variable] {
+                Location::Register(register) => tokens[index] = Token::NumU(register as u64),
+                Location::Memory(address) => {
+                    let load = matches!(&tokens[index - 1], Token::OpCode(word) if OpCode::from(word) == OpCode::Load);
+                    tokens[index - 1] = Token::OpCode(if load { "LOADM" } else { "STOREM" }.to_string());
+                    tokens[index] = Token::NumU(address as u64);
+                }
+            }
+        }
+
+        Ok((tokens, Allocation { variables: names.into_iter().map(|(file, name)| label(file, &name)).zip(locations).collect() }))
+    }
+
+    fn is_access(token: &Token) -> bool {
+        matches!(token, Token::OpCode(word) if matches!(OpCode::from(word), OpCode::Load | OpCode::Store))
+    }
+
+    fn is_memory_access(token: &Token) -> bool {
+        matches!(token, Token::OpCode(word) if matches!(OpCode::from(word), OpCode::LoadM | OpCode::StoreM))
+    }
+
+    //which variables are needed at the same time, and so cannot share a location
+    fn interference(list: &InstructionList, variables: &[Option<usize>], count: usize) -> Vec<HashSet<usize>> {
+        let len = list.instructions.len();
+        let mut successors = ControlFlowGraph::new(list).successors;
+
+        //every RETURN that can be reached from a function's entry without going through another call leads back to the instruction after the call
+        for (index, instruction) in list.instructions.iter().enumerate() {
+            if instruction.opcode != OpCode::Call || index + 1 >= len {
+                continue
+            }
+
+            let mut seen = vec![false; len];
+            let mut pending: Vec<usize> = instruction.target.into_iter().filter(|target| *target < len).collect();
+            while let Some(current) = pending.pop() {
+                if seen[current] {
+                    continue
+                }
+                seen[current] = true;
+
+                match list.instructions[current].opcode {
+                    OpCode::Return => {
+                        if !successors[current].contains(&(index + 1)) {
+                            successors[current].push(index + 1);
+                        }
+                    },
+                    OpCode::Call => if current + 1 < len {
+                        pending.push(current + 1)
+                    },
+                    _ => pending.extend(successors[current].iter().copied())
+                }
+            }
+        }
+
+        //variables which may be read later, before each instruction runs
+        let mut live_in: Vec<HashSet<usize>> = vec![HashSet::new(); len];
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for index in (0..len).rev() {
+                let mut live = RegisterAllocator::live_out(&successors[index], &live_in);
+                match (list.instructions[index].opcode, variables[index]) {
+                    (OpCode::Store, Some(variable)) => {
+                        live.remove(&variable);
+                    },
+                    (OpCode::Load, Some(variable)) => {
+                        live.insert(variable);
+                    },
+                    _ => ()
+                }
+
+                if live != live_in[index] {
+                    live_in[index] = live;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut interference = vec![HashSet::new(); count];
+        for index in 0..len {
+            if let (OpCode::Store, Some(variable)) = (list.instructions[index].opcode, variables[index]) {
+                for other in RegisterAllocator::live_out(&successors[index], &live_in) {
+                    if other != variable {
+                        interference[variable].insert(other);
+                        interference[other].insert(variable);
+                    }
+                }
+            }
+        }
+
+        //variables read before they are written rely on starting at 0, so they can't share with anything
+        if let Some(start) = live_in.first() {
+            for variable in start {
+                for other in 0..count {
+                    if other != *variable {
+                        interference[*variable].insert(other);
+                        interference[other].insert(*variable);
+                    }
+                }
+            }
+        }
+
+        interference
+    }
+
+    fn live_out(successors: &[usize], live_in: &[HashSet<usize>]) -> HashSet<usize> {
+        successors.iter().flat_map(|successor| live_in[*successor].iter().copied()).collect()
+    }
+
+    //gives each variable the lowest free register, or the lowest free memory address once those run out
+    fn colour(interference: &[HashSet<usize>], reserved: &HashSet<usize>, reserved_memory: &HashSet<usize>) -> Vec<Location> {
+        let mut locations: Vec<Location> = Vec::new();
+
+        for neighbours in interference {
+            let taken: HashSet<Location> = neighbours.iter().filter_map(|neighbour| locations.get(*neighbour).copied()).collect();
+
+            let register = (0..REGISTER_COUNT).find(|register| !reserved.contains(register) && !taken.contains(&Location::Register(*register)));
+            let location = match register {
+                Some(register) => Location::Register(register),
+                None => Location::Memory((0..).find(|address| !reserved_memory.contains(address) && !taken.contains(&Location::Memory(*address))).unwrap())
+            };
+
+            locations.push(location);
+        }
+
+        locations
+    }
+}
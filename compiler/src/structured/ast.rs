@@ -0,0 +1,109 @@
+//types that variables, parameters and return values can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    U64,
+    I64,
+    F64
+}
+
+impl Type {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::U64 => "u64",
+            Type::I64 => "i64",
+            Type::F64 => "f64"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "u64" => Some(Type::U64),
+            "i64" => Some(Type::I64),
+            "f64" => Some(Type::F64),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    //whole numbers take the type of whatever they are used with
+    Int(u64),
+    Float(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub line: usize
+}
+
+//compares two values, with a lone value being compared to 0 using !=
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub left: Expr,
+    pub op: CompareOp,
+    pub right: Expr
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintArg {
+    Value(Expr),
+    Text(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Let(String, Type, Expr),
+    Assign(String, Expr),
+    If(Condition, Vec<Stmt>, Vec<Stmt>),
+    While(Condition, Vec<Stmt>),
+    Return(Option<Expr>),
+    Print(PrintArg),
+    Expr(Expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub line: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(String, Type)>,
+    pub returns: Option<Type>,
+    pub body: Vec<Stmt>,
+    pub line: usize
+}
+
+//functions can be written anywhere, while the other statements make up the code that runs from the start
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub main: Vec<Stmt>
+}
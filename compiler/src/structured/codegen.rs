@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use virtual_machine::instruction::OpCode;
+
+use crate::{error::CompError, expr::{self, ExprTarget, Operand}, ir::{Instruction, InstructionList}, token::Token};
+
+use super::ast::*;
+
+//variables are kept in registers 0 to 6, while register 7 holds return values as registers are restored after a call
+const VARIABLE_REGISTERS: usize = 7;
+const SCRATCH_REGISTER: u64 = 7;
+
+/*
+Lowers a program's syntax tree into instructions.
+Values are converted to the type of the variable, parameter or return value they are used as.
+Calls push the caller's registers onto the stack before the arguments and restore them afterwards, so functions can use every register and call themselves.
+*/
+pub struct CodeGen {
+    list: InstructionList,
+
+    //parameter types and return type of every function
+    signatures: HashMap<String, (Vec<Type>, Option<Type>)>,
+
+    //every CALL and the function it calls, which are given targets once all functions are generated
+    calls: Vec<(usize, String)>,
+
+    //register and type of each variable in the function being generated
+    scope: HashMap<String, (usize, Type)>,
+
+    //return type of the function being generated, which is None for code outside of functions
+    returns: Option<Option<Type>>
+}
+
+impl CodeGen {
+    pub fn generate(program: &Program) -> Result<InstructionList, CompError> {
+        let mut gen = CodeGen {
+            list: InstructionList { instructions: Vec::new(), end_labels: Vec::new() },
+            signatures: HashMap::new(),
+            calls: Vec::new(),
+            scope: HashMap::new(),
+            returns: None
+        };
+
+        for function in &program.functions {
+            let signature = (function.params.iter().map(|(_, ty)| *ty).collect(), function.returns);
+            if gen.signatures.insert(function.name.clone(), signature).is_some() {
+                return Err(CompError::UnexpectedChar(format!("Function '{}' on line {} is already defined", function.name, function.line)))
+            }
+        }
+
+        gen.statements(&program.main)?;
+        gen.emit(OpCode::Halt, None, program.main.last().map_or(1, |stmt| stmt.line));
+
+        let mut entries = HashMap::new();
+        for function in &program.functions {
+            entries.insert(function.name.clone(), gen.list.instructions.len());
+            gen.function(function)?;
+        }
+
+        for (index, name) in std::mem::take(&mut gen.calls) {
+            gen.list.instructions[index].target = Some(entries[&name]);
+        }
+
+        Ok(gen.list)
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), CompError> {
+        if function.params.len() > VARIABLE_REGISTERS {
+            return Err(CompError::Overflow(format!("Function '{}' on line {} has more than {} parameters", function.name, function.line, VARIABLE_REGISTERS)))
+        }
+
+        self.scope = HashMap::new();
+        self.returns = Some(function.returns);
+        let entry = self.list.instructions.len();
+
+        //arguments are on the stack in order, so the last one is stored first
+        for (register, (name, ty)) in function.params.iter().enumerate() {
+            if self.scope.insert(name.clone(), (register, *ty)).is_some() {
+                return Err(CompError::UnexpectedChar(format!("Function '{}' on line {} has two parameters called '{}'", function.name, function.line, name)))
+            }
+        }
+        for register in (0..function.params.len()).rev() {
+            self.emit(OpCode::Store, Some(Token::NumU(register as u64)), function.line);
+        }
+
+        self.statements(&function.body)?;
+
+        //functions that reach their end return nothing, or 0
+        let ends_with_return = matches!(function.body.last(), Some(Stmt { kind: StmtKind::Return(_), .. }));
+        if !ends_with_return {
+            let line = function.body.last().map_or(function.line, |stmt| stmt.line);
+            if let Some(ty) = function.returns {
+                expr::lower(self, &Expr { kind: ExprKind::Int(0), line }, ty)?;
+            }
+            self.emit(OpCode::Return, None, line);
+        }
+
+        self.list.instructions[entry].labels.push(function.name.clone());
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: OpCode, operand: Option<Token>, line: usize) -> usize {
+        self.list.instructions.push(Instruction {
+            opcode,
+            word: format!("{:?}", opcode).to_uppercase(),
+            operand,
+            target: None,
+            labels: Vec::new(),
+            line,
+            message: None
+        });
+
+        self.list.instructions.len() - 1
+    }
+
+    //jumps are given a placeholder operand, which is replaced once the target is known
+    fn emit_jump(&mut self, opcode: OpCode, target: Option<usize>, line: usize) -> usize {
+        let index = self.emit(opcode, Some(Token::NumU(0)), line);
+        self.list.instructions[index].target = target;
+        index
+    }
+
+    fn land_here(&mut self, jumps: &[usize]) {
+        let here = self.list.instructions.len();
+        for jump in jumps {
+            self.list.instructions[*jump].target = Some(here);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Result<(), CompError> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CompError> {
+        let line = statement.line;
+
+        match &statement.kind {
+            StmtKind::Let(name, ty, value) => {
+                if self.scope.contains_key(name) {
+                    return Err(CompError::UnexpectedChar(format!("Variable '{}' on line {} is already defined", name, line)))
+                }
+                if self.scope.len() >= VARIABLE_REGISTERS {
+                    return Err(CompError::Overflow(format!("Variable '{}' on line {} is one more than the {} variables allowed in a function", name, line, VARIABLE_REGISTERS)))
+                }
+
+                expr::lower(self, value, *ty)?;
+                let register = self.scope.len();
+                self.scope.insert(name.clone(), (register, *ty));
+                self.emit(OpCode::Store, Some(Token::NumU(register as u64)), line);
+            },
+
+            StmtKind::Assign(name, value) => {
+                let (register, ty) = self.variable(name, line)?;
+                expr::lower(self, value, ty)?;
+                self.emit(OpCode::Store, Some(Token::NumU(register as u64)), line);
+            },
+
+            StmtKind::If(condition, then, otherwise) => {
+                let to_otherwise = self.condition(condition)?;
+                self.statements(then)?;
+
+                if otherwise.is_empty() {
+                    self.land_here(&to_otherwise);
+                }
+                else {
+                    let to_end = self.emit_jump(OpCode::JMP, None, line);
+                    self.land_here(&to_otherwise);
+                    self.statements(otherwise)?;
+                    self.land_here(&[to_end]);
+                }
+            },
+
+            StmtKind::While(condition, body) => {
+                let start = self.list.instructions.len();
+                let to_end = self.condition(condition)?;
+                self.statements(body)?;
+                self.emit_jump(OpCode::JMP, Some(start), line);
+                self.land_here(&to_end);
+            },
+
+            StmtKind::Return(value) => {
+                match (self.returns, value) {
+                    (None, _) => return Err(CompError::UnexpectedChar(format!("'return' on line {} is outside of a function", line))),
+                    (Some(Some(ty)), Some(value)) => expr::lower(self, value, ty)?,
+                    (Some(None), None) => (),
+                    (Some(Some(ty)), None) => return Err(CompError::UnexpectedChar(format!("'return' on line {} needs a {} value", line, ty.name()))),
+                    (Some(None), Some(_)) => return Err(CompError::UnexpectedChar(format!("'return' on line {} is in a function which doesn't return a value", line)))
+                }
+
+                self.emit(OpCode::Return, None, line);
+            },
+
+            StmtKind::Print(PrintArg::Value(value)) => {
+                let ty = expr::infer(self, value)?.unwrap_or(Type::U64);
+                expr::lower(self, value, ty)?;
+
+                let format = match ty {
+                    Type::U64 => 0,
+                    Type::I64 => 1,
+                    Type::F64 => 2
+                };
+                self.emit(OpCode::Print, Some(Token::NumU(format)), line);
+            },
+
+            StmtKind::Print(PrintArg::Text(text)) => {
+                for c in text.chars() {
+                    self.emit(OpCode::Push, Some(Token::NumU(c as u64)), line);
+                }
+                self.emit(OpCode::PrintSTR, Some(Token::NumU(text.chars().count() as u64)), line);
+            },
+
+            StmtKind::Expr(Expr { kind: ExprKind::Call(name, args), .. }) => {
+                if self.call_function(name, args, line)?.is_some() {
+                    self.emit(OpCode::Pop, None, line);
+                }
+            },
+
+            StmtKind::Expr(_) => return Err(CompError::UnexpectedChar(format!("Statement on line {} does nothing", line)))
+        }
+
+        Ok(())
+    }
+
+    fn variable(&self, name: &str, line: usize) -> Result<(usize, Type), CompError> {
+        match self.scope.get(name) {
+            Some(variable) => Ok(*variable),
+            None => Err(CompError::UnexpectedChar(format!("Variable '{}' on line {} doesn't exist", name, line)))
+        }
+    }
+
+    //calls a function, returning the type of the value it leaves on the stack
+    fn call_function(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Option<Type>, CompError> {
+        let (params, returns) = match self.signatures.get(name) {
+            Some(signature) => signature.clone(),
+            None => return Err(CompError::UnexpectedChar(format!("Function '{}' on line {} doesn't exist", name, line)))
+        };
+
+        if params.len() != args.len() {
+            return Err(CompError::UnexpectedChar(format!("Function '{}' on line {} takes {} argument(s) but was given {}", name, line, params.len(), args.len())))
+        }
+
+        let saved = self.scope.len();
+        for register in 0..saved {
+            self.emit(OpCode::Load, Some(Token::NumU(register as u64)), line);
+        }
+
+        for (arg, ty) in args.iter().zip(params) {
+            expr::lower(self, arg, ty)?;
+        }
+
+        let call = self.emit_jump(OpCode::Call, None, line);
+        self.calls.push((call, name.to_string()));
+
+        //the return value is moved out of the way while the registers are restored from underneath it
+        if saved > 0 && returns.is_some() {
+            self.emit(OpCode::Store, Some(Token::NumU(SCRATCH_REGISTER)), line);
+        }
+        for register in (0..saved).rev() {
+            self.emit(OpCode::Store, Some(Token::NumU(register as u64)), line);
+        }
+        if saved > 0 && returns.is_some() {
+            self.emit(OpCode::Load, Some(Token::NumU(SCRATCH_REGISTER)), line);
+        }
+
+        Ok(returns)
+    }
+
+    //compares the two sides of a condition, returning the jumps taken when it is false
+    fn condition(&mut self, condition: &Condition) -> Result<Vec<usize>, CompError> {
+        let ty = expr::combine(expr::infer(self, &condition.left)?, expr::infer(self, &condition.right)?).unwrap_or(Type::U64);
+        expr::lower(self, &condition.left, ty)?;
+        expr::lower(self, &condition.right, ty)?;
+
+        let line = condition.left.line;
+        let kind = match ty {
+            Type::U64 => 0,
+            Type::I64 => 1,
+            Type::F64 => 2
+        };
+        self.emit(OpCode::CMP, Some(Token::NumU(kind)), line);
+
+        let jumps: &[OpCode] = match condition.op {
+            CompareOp::Equal => &[OpCode::JNE],
+            CompareOp::NotEqual => &[OpCode::JE],
+            CompareOp::Less => &[OpCode::JG, OpCode::JE],
+            CompareOp::Greater => &[OpCode::JL, OpCode::JE],
+            CompareOp::LessEqual => &[OpCode::JG],
+            CompareOp::GreaterEqual => &[OpCode::JL]
+        };
+
+        Ok(jumps.iter().map(|jump| self.emit_jump(*jump, None, line)).collect())
+    }
+}
+
+impl ExprTarget for CodeGen {
+    fn emit(&mut self, opcode: OpCode, operand: Option<Token>, line: usize) {
+        CodeGen::emit(self, opcode, operand, line);
+    }
+
+    fn operand(&self, name: &str, line: usize) -> Result<Operand, CompError> {
+        let (register, ty) = self.variable(name, line)?;
+        Ok(Operand::Variable(Token::NumU(register as u64), ty))
+    }
+
+    fn returns(&self, name: &str, line: usize) -> Result<Option<Type>, CompError> {
+        match self.signatures.get(name) {
+            Some((_, returns)) => Ok(*returns),
+            None => Err(CompError::UnexpectedChar(format!("Function '{}' on line {} doesn't exist", name, line)))
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<(), CompError> {
+        self.call_function(name, args, line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+/*
+A small structured language that compiles to the same instructions as .nar files, written in .nrs files.
+It has u64, i64 and f64 variables, arithmetic, if / else, while loops, functions with parameters and return values, and print.
+*/
+pub mod ast;
+pub mod scanner;
+pub mod parser;
+pub mod codegen;
+
+use crate::{error::CompError, ir::InstructionList};
+
+use self::{codegen::CodeGen, parser::StructuredParser, scanner::Scanner};
+
+pub fn compile_to_instructions(input: &str) -> Result<InstructionList, CompError> {
+    let program = StructuredParser::parse(Scanner::scan(input)?)?;
+    CodeGen::generate(&program)
+}
@@ -0,0 +1,240 @@
+use crate::{error::CompError, expr::ExprParser};
+
+use super::{ast::*, scanner::SToken};
+
+const KEYWORDS: [&str; 10] = ["fn", "let", "if", "else", "while", "return", "print", "u64", "i64", "f64"];
+
+//turns scanned tokens into a program's syntax tree
+pub struct StructuredParser {
+    tokens: Vec<(SToken, usize)>,
+    index: usize
+}
+
+impl StructuredParser {
+    pub fn parse(tokens: Vec<(SToken, usize)>) -> Result<Program, CompError> {
+        let mut parser = StructuredParser { tokens, index: 0 };
+        let mut program = Program { functions: Vec::new(), main: Vec::new() };
+
+        while parser.peek().is_some() {
+            if parser.peek_ident("fn") {
+                program.functions.push(parser.function()?);
+            }
+            else {
+                program.main.push(parser.statement()?);
+            }
+        }
+
+        Ok(program)
+    }
+
+    fn peek(&self) -> Option<&SToken> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    //line of the next token, or of the last token at the end of the program
+    fn line(&self) -> usize {
+        self.tokens.get(self.index).or_else(|| self.tokens.last()).map_or(1, |(_, line)| *line)
+    }
+
+    fn peek_ident(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(SToken::Ident(ident)) if ident == word)
+    }
+
+    fn peek_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(SToken::Symbol(found)) if *found == symbol)
+    }
+
+    fn next(&mut self) -> Result<SToken, CompError> {
+        match self.tokens.get(self.index) {
+            Some((token, _)) => {
+                self.index += 1;
+                Ok(token.clone())
+            },
+            None => Err(CompError::UnexpectedEOF(format!("Program ended unexpectedly on line {}", self.line())))
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), CompError> {
+        let line = self.line();
+        match self.next()? {
+            SToken::Symbol(found) if found == symbol => Ok(()),
+            found => Err(CompError::UnexpectedChar(format!("Expected '{}' on line {} but found {}", symbol, line, found.describe())))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, CompError> {
+        let line = self.line();
+        match self.next()? {
+            SToken::Ident(name) if !KEYWORDS.contains(&name.as_str()) => Ok(name),
+            found => Err(CompError::UnexpectedChar(format!("Expected a name on line {} but found {}", line, found.describe())))
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, CompError> {
+        let line = self.line();
+        match self.next()? {
+            SToken::Ident(name) if name == "u64" => Ok(Type::U64),
+            SToken::Ident(name) if name == "i64" => Ok(Type::I64),
+            SToken::Ident(name) if name == "f64" => Ok(Type::F64),
+            found => Err(CompError::UnexpectedChar(format!("Expected a type (u64, i64 or f64) on line {} but found {}", line, found.describe())))
+        }
+    }
+
+    //fn name(param: type, ...) -> type { ... }
+    fn function(&mut self) -> Result<Function, CompError> {
+        let line = self.line();
+        self.next()?;
+        let name = self.name()?;
+
+        self.expect_symbol("(")?;
+        let mut params = Vec::new();
+        while !self.peek_symbol(")") {
+            if !params.is_empty() {
+                self.expect_symbol(",")?;
+            }
+
+            let param = self.name()?;
+            self.expect_symbol(":")?;
+            params.push((param, self.ty()?));
+        }
+        self.expect_symbol(")")?;
+
+        let returns = if self.peek_symbol("->") {
+            self.next()?;
+            Some(self.ty()?)
+        }
+        else {
+            None
+        };
+
+        Ok(Function { name, params, returns, body: self.block()?, line })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompError> {
+        self.expect_symbol("{")?;
+
+        let mut statements = Vec::new();
+        while !self.peek_symbol("}") {
+            if self.peek().is_none() {
+                return Err(CompError::UnexpectedEOF(format!("Block is missing its closing '}}' on line {}", self.line())))
+            }
+            statements.push(self.statement()?);
+        }
+
+        self.expect_symbol("}")?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompError> {
+        let line = self.line();
+
+        let kind = match self.peek() {
+            Some(SToken::Ident(word)) if word == "let" => {
+                self.next()?;
+                let name = self.name()?;
+                self.expect_symbol(":")?;
+                let ty = self.ty()?;
+                self.expect_symbol("=")?;
+                let value = self.expression()?;
+                self.expect_symbol(";")?;
+                StmtKind::Let(name, ty, value)
+            },
+
+            Some(SToken::Ident(word)) if word == "if" => return self.if_statement(),
+
+            Some(SToken::Ident(word)) if word == "while" => {
+                self.next()?;
+                let condition = self.condition()?;
+                StmtKind::While(condition, self.block()?)
+            },
+
+            Some(SToken::Ident(word)) if word == "return" => {
+                self.next()?;
+                let value = if self.peek_symbol(";") { None } else { Some(self.expression()?) };
+                self.expect_symbol(";")?;
+                StmtKind::Return(value)
+            },
+
+            Some(SToken::Ident(word)) if word == "print" => {
+                self.next()?;
+                self.expect_symbol("(")?;
+                let arg = match self.peek() {
+                    Some(SToken::Str(text)) => {
+                        let text = text.clone();
+                        self.next()?;
+                        PrintArg::Text(text)
+                    },
+                    _ => PrintArg::Value(self.expression()?)
+                };
+                self.expect_symbol(")")?;
+                self.expect_symbol(";")?;
+                StmtKind::Print(arg)
+            },
+
+            Some(SToken::Ident(_)) if self.tokens.get(self.index + 1).map(|(token, _)| token) == Some(&SToken::Symbol("=")) => {
+                let name = self.name()?;
+                self.next()?;
+                let value = self.expression()?;
+                self.expect_symbol(";")?;
+                StmtKind::Assign(name, value)
+            },
+
+            _ => {
+                let value = self.expression()?;
+                self.expect_symbol(";")?;
+                StmtKind::Expr(value)
+            }
+        };
+
+        Ok(Stmt { kind, line })
+    }
+
+    //if condition { ... } else if condition { ... } else { ... }
+    fn if_statement(&mut self) -> Result<Stmt, CompError> {
+        let line = self.line();
+        self.next()?;
+
+        let condition = self.condition()?;
+        let then = self.block()?;
+
+        let otherwise = if self.peek_ident("else") {
+            self.next()?;
+            if self.peek_ident("if") {
+                vec![self.if_statement()?]
+            }
+            else {
+                self.block()?
+            }
+        }
+        else {
+            Vec::new()
+        };
+
+        Ok(Stmt { kind: StmtKind::If(condition, then, otherwise), line })
+    }
+
+    fn condition(&mut self) -> Result<Condition, CompError> {
+        let left = self.expression()?;
+
+        let op = match self.peek() {
+            Some(SToken::Symbol("==")) => CompareOp::Equal,
+            Some(SToken::Symbol("!=")) => CompareOp::NotEqual,
+            Some(SToken::Symbol("<")) => CompareOp::Less,
+            Some(SToken::Symbol(">")) => CompareOp::Greater,
+            Some(SToken::Symbol("<=")) => CompareOp::LessEqual,
+            Some(SToken::Symbol(">=")) => CompareOp::GreaterEqual,
+            _ => {
+                let line = left.line;
+                return Ok(Condition { left, op: CompareOp::NotEqual, right: Expr { kind: ExprKind::Int(0), line } })
+            }
+        };
+        self.next()?;
+
+        Ok(Condition { left, op, right: self.expression()? })
+    }
+
+    //infix expressions are shared with .nar files
+    fn expression(&mut self) -> Result<Expr, CompError> {
+        ExprParser::parse(&self.tokens, &mut self.index)
+    }
+}
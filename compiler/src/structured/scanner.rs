@@ -0,0 +1,115 @@
+use crate::error::CompError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SToken {
+    //names and keywords
+    Ident(String),
+    Int(u64),
+    Float(f64),
+    Str(String),
+    Symbol(&'static str)
+}
+
+impl SToken {
+    //how a token is written in error messages
+    pub fn describe(&self) -> String {
+        match self {
+            SToken::Ident(name) => format!("'{}'", name),
+            SToken::Int(num) => format!("'{}'", num),
+            SToken::Float(num) => format!("'{}'", num),
+            SToken::Str(text) => format!("\"{}\"", text),
+            SToken::Symbol(symbol) => format!("'{}'", symbol)
+        }
+    }
+}
+
+const SYMBOLS: [&str; 20] = ["->", "==", "!=", "<=", ">=", "(", ")", "{", "}", ",", ":", ";", "=", "<", ">", "+", "-", "*", "/", "%"];
+
+//splits structured source into tokens, each paired with the line it is on
+pub struct Scanner;
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner::new()
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Scanner {
+        Scanner
+    }
+
+    pub fn scan(input: &str) -> Result<Vec<(SToken, usize)>, CompError> {
+        let input: Vec<char> = input.chars().collect();
+        let mut output = Vec::new();
+        let mut index = 0;
+        let mut line = 1;
+
+        while index < input.len() {
+            let c = input[index];
+            let start = index;
+
+            match c {
+                '\n' => {
+                    line += 1;
+                    index += 1;
+                },
+                c if c.is_whitespace() => index += 1,
+
+                //comments run to the end of the line
+                '/' if input.get(index + 1) == Some(&'/') => {
+                    while index < input.len() && input[index] != '\n' {
+                        index += 1;
+                    }
+                },
+
+                c if c.is_alphabetic() || c == '_' => {
+                    while index < input.len() && (input[index].is_alphanumeric() || input[index] == '_') {
+                        index += 1;
+                    }
+                    output.push((SToken::Ident(input[start..index].iter().collect()), line));
+                },
+
+                c if c.is_ascii_digit() => {
+                    while index < input.len() && (input[index].is_ascii_digit() || input[index] == '.' || input[index] == '_') {
+                        index += 1;
+                    }
+
+                    let text: String = input[start..index].iter().filter(|c| **c != '_').collect();
+                    let token = match (text.parse::<u64>(), text.parse::<f64>()) {
+                        (Ok(num), _) => SToken::Int(num),
+                        (_, Ok(num)) if text.contains('.') => SToken::Float(num),
+                        _ => return Err(CompError::Overflow(format!("'{}' on line {} is not a valid number", text, line)))
+                    };
+                    output.push((token, line));
+                },
+
+                '"' => {
+                    index += 1;
+                    while index < input.len() && input[index] != '"' && input[index] != '\n' {
+                        index += 1;
+                    }
+
+                    if input.get(index) != Some(&'"') {
+                        return Err(CompError::UnexpectedEOF(format!("Text on line {} is missing its closing '\"'", line)))
+                    }
+                    index += 1;
+                    output.push((SToken::Str(input[start + 1..index - 1].iter().collect()), line));
+                },
+
+                _ => {
+                    let symbol = SYMBOLS.iter().find(|symbol| symbol.chars().enumerate().all(|(i, s)| input.get(index + i) == Some(&s)));
+                    match symbol {
+                        Some(symbol) => {
+                            index += symbol.len();
+                            output.push((SToken::Symbol(symbol), line));
+                        },
+                        None => return Err(CompError::UnexpectedChar(format!("Unexpected '{}' on line {}", c, line)))
+                    }
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
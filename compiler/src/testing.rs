@@ -0,0 +1,263 @@
+use std::fs;
+use std::path::Path;
+
+use virtual_machine::error::{ASSERT_CODE, VmError};
+use virtual_machine::output::SharedBuffer;
+use virtual_machine::vm::Machine;
+
+use crate::{compiler::Compiler, error::CompError, lexer::Lexer, program::Program, token::Token};
+
+//fuel given to each test when none is chosen
+pub const DEFAULT_FUEL: u64 = 1_000_000;
+
+//an expectation checked when a test reaches the line it was written on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assertion {
+    //ASSERT_EQ value, which pops the top of the stack and compares it with value
+    Stack(Token),
+
+    //ASSERT_REG register value
+    Register(usize, Token),
+
+    //ASSERT_OUTPUT "text", compared with everything the test has printed so far
+    Output(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Test {
+    pub name: String,
+
+    //line of the test's header
+    pub line: usize,
+
+    //address of the test's first instruction
+    pub entry: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub line: usize,
+
+    //line the test failed on and why, if it failed
+    pub failure: Option<(usize, String)>
+}
+
+/*
+Tests written in a .nar file as blocks that start with test "name": and carry on until the next test or function label.
+The whole file is compiled once, with each test becoming a separate entry point that ends with HALT, and each assertion a label marking where it is checked.
+Every test runs in a fresh machine from its entry point, so tests can call the file's functions without running the code at the top of the file.
+When compiled normally, test blocks are left out of the program.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestSuite {
+    pub program: Program,
+    pub tests: Vec<Test>,
+
+    //address each assertion is checked at, along with the line it was written on
+    pub assertions: Vec<(usize, usize, Assertion)>
+}
+
+impl TestSuite {
+    pub fn build_file<P: AsRef<Path>>(path: P) -> Result<TestSuite, CompError> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path).map_err(|e| CompError::Io(format!("Cannot read '{}': {}", path.display(), e)))?;
+        TestSuite::build(&input, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    //compiles the tests in input, with any included files found relative to dir
+    pub fn build(input: &str, dir: &Path) -> Result<TestSuite, CompError> {
+        let original: Vec<&str> = input.lines().collect();
+        let mut lines: Vec<String> = original.iter().map(|line| line.to_string()).collect();
+        let mut names = Vec::new();
+        let mut assertions = Vec::new();
+
+        //lines are replaced one for one, so line numbers stay the same
+        for (start, end) in TestSuite::blocks(input) {
+            let header = original[start].trim();
+            let name = &header["test \"".len()..header.len() - 2];
+            if name.trim().is_empty() {
+                return Err(CompError::UnexpectedChar(format!("Test on line {} needs a name between the quotes", start + 1)))
+            }
+            names.push((name.to_string(), start + 1));
+
+            //HALT ends the code before the test, which may be another test
+            lines[start] = format!("HALT test@{}:", names.len() - 1);
+
+            for (index, line) in lines.iter_mut().enumerate().take(end).skip(start + 1) {
+                if let Some(assertion) = TestSuite::assertion(line, index + 1)? {
+                    *line = format!("assert@{}:", assertions.len());
+                    assertions.push((index + 1, assertion));
+                }
+            }
+
+            match lines.get_mut(end) {
+                Some(line) => line.insert_str(0, "HALT "),
+                None => lines.push("HALT".into())
+            }
+        }
+
+        if names.is_empty() {
+            return Err(CompError::NoTests("No tests found, tests are written as test \"name\":".into()))
+        }
+
+        let mut compiler = Compiler::default();
+        let program = compiler.build_relative(lines.join("\n"), dir, false)?.clone();
+
+        let address = |label: String| program.symbol(&label).ok_or_else(|| CompError::Impossible(format!("Label '{}' is missing from the compiled tests", label)));
+        let tests = names.into_iter().enumerate().map(|(index, (name, line))| Ok(Test { name, line, entry: address(format!("test@{}", index))? })).collect::<Result<_, CompError>>()?;
+        let assertions = assertions.into_iter().enumerate().map(|(index, (line, assertion))| Ok((address(format!("assert@{}", index))?, line, assertion))).collect::<Result<_, CompError>>()?;
+
+        Ok(TestSuite { program, tests, assertions })
+    }
+
+    //input with its test blocks replaced by blank lines, for compiling the program without them
+    pub fn strip(input: &str) -> String {
+        let mut lines: Vec<&str> = input.lines().collect();
+        for (start, end) in TestSuite::blocks(input) {
+            lines[start..end].fill("");
+        }
+
+        lines.join("\n")
+    }
+
+    //first line of each test block and the line after its last
+    fn blocks(input: &str) -> Vec<(usize, usize)> {
+        let lines: Vec<&str> = input.lines().collect();
+        //the opening quote can't also be the closing one, as in test ":
+        let is_header = |line: &str| line.trim().len() >= "test \"\":".len() && line.trim().starts_with("test \"") && line.trim().ends_with("\":");
+
+        let mut blocks = Vec::new();
+        for (start, line) in lines.iter().enumerate() {
+            if !is_header(line) {
+                continue
+            }
+
+            //a function label starts at the beginning of its line, while the code in a test is indented
+            let end = (start + 1..lines.len())
+                .find(|index| is_header(lines[*index]) || !lines[*index].starts_with(char::is_whitespace) && lines[*index].trim_end().ends_with(':'))
+                .unwrap_or(lines.len());
+            blocks.push((start, end));
+        }
+
+        blocks
+    }
+
+    fn assertion(line: &str, number: usize) -> Result<Option<Assertion>, CompError> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(char::is_whitespace).map_or((line, ""), |(word, rest)| (word, rest.trim()));
+
+        let value = |text: &str| match Lexer::lex(text).as_deref() {
+            Ok([token]) if token.is_num() => Ok(token.clone()),
+            _ => Err(CompError::UnexpectedChar(format!("'{}' on line {} is not a number", text, number)))
+        };
+
+        match word {
+            "ASSERT_EQ" => Ok(Some(Assertion::Stack(value(rest)?))),
+            "ASSERT_REG" => {
+                let (register, expected) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                match register.parse() {
+                    Ok(register) if register < 8 => Ok(Some(Assertion::Register(register, value(expected.trim())?))),
+                    _ => Err(CompError::UnexpectedChar(format!("ASSERT_REG on line {} needs a register from 0 to 7 and a value", number)))
+                }
+            },
+            "ASSERT_OUTPUT" => match rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+                Some(text) => Ok(Some(Assertion::Output(text.replace("\\n", "\n").replace("\\t", "\t")))),
+                None => Err(CompError::UnexpectedChar(format!("ASSERT_OUTPUT on line {} needs text in double quotes", number)))
+            },
+            _ => Ok(None)
+        }
+    }
+
+    //runs every test, each given at most fuel instructions
+    pub fn run(&self, fuel: u64) -> Vec<TestResult> {
+        self.tests.iter().map(|test| TestResult { name: test.name.clone(), line: test.line, failure: self.run_test(test, fuel).err() }).collect()
+    }
+
+    fn run_test(&self, test: &Test, fuel: u64) -> Result<(), (usize, String)> {
+        let output = SharedBuffer::new();
+        let mut vm = Machine::new();
+        vm.set_output(Box::new(output.clone()));
+        vm.load(self.program.bytes.clone());
+        vm.program_address = test.entry - 1;
+
+        //address of the instruction run last, where a program that stops with an error stopped
+        let mut last = test.entry;
+        let mut used = 0;
+        while !vm.is_finished() {
+            let address = vm.program_address + 1;
+            last = address;
+            for (_, line, assertion) in self.assertions.iter().filter(|(at, _, _)| *at == address) {
+                TestSuite::check(&mut vm, &output, assertion).map_err(|e| (*line, e))?;
+            }
+
+            if used == fuel {
+                return Err((self.line(address).unwrap_or(test.line), format!("Ran out of fuel after {} instructions", fuel)))
+            }
+
+            vm.execute_instruction();
+            used += 1;
+        }
+
+        //an ASSERT, TRAP or anything else the program stopped on fails the test at its line
+        match vm.trap() {
+            Some(VmError::Trap { code, address, message }) => {
+                let reason = match (code, message) {
+                    (&ASSERT_CODE, Some(message)) => format!("ASSERT failed: {}", message),
+                    (&ASSERT_CODE, None) => "ASSERT failed".to_string(),
+                    (code, Some(message)) => format!("TRAP {}: {}", code, message),
+                    (code, None) => format!("TRAP {}", code)
+                };
+                Err((self.line(*address).unwrap_or(test.line), reason))
+            },
+            Some(VmError::Fault(message) | VmError::OutOfMemory(message)) => Err((self.line(last).unwrap_or(test.line), message.clone())),
+            Some(error) => Err((self.line(last).unwrap_or(test.line), format!("{:?}", error))),
+            None => Ok(())
+        }
+    }
+
+    fn check(vm: &mut Machine, output: &SharedBuffer, assertion: &Assertion) -> Result<(), String> {
+        match assertion {
+            Assertion::Stack(expected) => match vm.stack.pop() {
+                Some(found) if found == TestSuite::bits(expected) => Ok(()),
+                Some(found) => Err(format!("ASSERT_EQ expected {} on the stack but found {}", TestSuite::show(expected), TestSuite::show_as(expected, found))),
+                None => Err(format!("ASSERT_EQ expected {} on the stack but it was empty", TestSuite::show(expected)))
+            },
+            Assertion::Register(register, expected) => match vm.registers[*register] {
+                found if found == TestSuite::bits(expected) => Ok(()),
+                found => Err(format!("ASSERT_REG expected register {} to be {} but found {}", register, TestSuite::show(expected), TestSuite::show_as(expected, found)))
+            },
+            Assertion::Output(expected) => match output.to_string_lossy() {
+                found if found == *expected => Ok(()),
+                found => Err(format!("ASSERT_OUTPUT expected {:?} to have been printed but found {:?}", expected, found))
+            }
+        }
+    }
+
+    fn bits(value: &Token) -> u64 {
+        match value {
+            Token::NumU(num) => *num,
+            Token::NumI(num) => *num as u64,
+            Token::NumF(num) => num.to_bits(),
+            _ => 0
+        }
+    }
+
+    fn show(value: &Token) -> String {
+        TestSuite::show_as(value, TestSuite::bits(value))
+    }
+
+    //found values are shown as the same type as the expected value
+    fn show_as(expected: &Token, found: u64) -> String {
+        match expected {
+            Token::NumI(_) => (found as i64).to_string(),
+            Token::NumF(_) => f64::from_bits(found).to_string(),
+            _ => found.to_string()
+        }
+    }
+
+    //source line of the instruction at address, if it has one
+    fn line(&self, address: usize) -> Option<usize> {
+        self.program.line_table.iter().find(|(at, _)| *at == address).map(|(_, line)| *line)
+    }
+}
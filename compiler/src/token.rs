@@ -1,49 +1,31 @@
-use virtual_machine::instruction::OpCode;
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     OpCode (String),
     Func (String),
     NumU (u64),
     NumI (i64),
-    NumF (f64)
+    NumF (f64),
+
+    //text in double quotes, given as the message of an ASSERT or TRAP
+    Text (String)
 }
 
 impl Token {
     pub fn is_push(&self) -> bool {
-        match self {
-            Token::OpCode(s) => {
-                if s == "PUSH" || s == "Push" {
-                    true
-                }
-                else {
-                    false
-                }
-            },
-            _ => false
-        }
+        matches!(self, Token::OpCode(s) if s == "PUSH" || s == "Push")
+    }
+
+    pub fn is_call(&self) -> bool {
+        matches!(self, Token::OpCode(s) if s == "CALL" || s == "Call")
     }
 
 
     pub fn is_conditional_jmp(&self) -> bool {
-        match self {
-            Token::OpCode(s) => {
-                if s == "JE" || s == "JNE" || s == "JG" || s == "JL" {
-                    true
-                }
-                else {
-                    false
-                }
-            },
-            _ => false
-        }
+        matches!(self, Token::OpCode(s) if s == "JE" || s == "JNE" || s == "JG" || s == "JL")
     }
 
     pub fn is_num(&self) -> bool {
-        match self {
-            Token::NumU(_) | Token::NumI(_) | Token::NumF(_) => true,
-            _ => false
-        }
+        matches!(self, Token::NumU(_) | Token::NumI(_) | Token::NumF(_))
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use compiler::compiler::Compiler;
+use compiler::disasm::Disassembler;
+use compiler::error::CompError;
+use compiler::testing::{DEFAULT_FUEL, TestSuite};
+use virtual_machine::error::{ASSERT_CODE, VmError};
+use virtual_machine::trace::JsonLinesTracer;
+use virtual_machine::vm::Machine;
+
+use crate::repl::Repl;
+use crate::watch::Watcher;
+
+//exit codes for when something goes wrong, as a program that runs successfully exits with 0
+pub const TEST_FAILURE: i32 = 1;
+pub const USAGE_ERROR: i32 = 64;
+pub const COMPILE_ERROR: i32 = 65;
+pub const RUNTIME_ERROR: i32 = 70;
+pub const IO_ERROR: i32 = 74;
+
+
+const HELP: &str = "Usage: nariva <command> [options]
+
+Commands:
+    build <file.nar|file.nrs>   Compile a program into a .binar file
+    run <file.binar>            Run a compiled program
+    exec <file.nar|file.nrs>    Compile a program in memory and run it
+    disasm <file.binar>         Turn a compiled program back into .nar source
+    repl                        Type in instructions and run them one at a time
+    watch <file.nar|file.nrs>   Compile and run a program again every time it or a file it includes changes
+    test <file.nar>             Run the tests written in a .nar file
+    help                        Show this message
+
+Options:
+    -o, --output <path>         Where build and disasm write to (build defaults to the input with a .binar extension, disasm to stdout)
+    -O, --optimize              Optimize the program while compiling
+    --listing                   Also write a .lst listing next to the output when building
+    --trace <path>              Write every executed instruction to path as JSON lines, or to stderr if path is -
+    --fuel <count>              Stop with an error after running this many instructions (watch and test default to 1000000 for each run)
+    --exit-with-result          Exit run and exec with the last number on the stack, cut down to its lowest 8 bits, instead of 0
+
+A program that runs to the end exits with 0, after printing the last number on its stack if there is one, and test exits with 1 if any test fails.
+Otherwise the exit code is 64 for incorrect usage, 65 for compile errors, 70 for runtime errors and 74 for files that can't be read or written.";
+
+//an error message along with the exit code it causes
+pub(crate) type Failure = (i32, String);
+
+//flags and arguments given after the command
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Options {
+    pub(crate) input: Option<String>,
+    pub(crate) output: Option<String>,
+    pub(crate) optimize: bool,
+    pub(crate) listing: bool,
+    pub(crate) trace: Option<String>,
+    pub(crate) fuel: Option<u64>,
+
+    //whether the program's result is the exit code, for scripts that need the result apart from what the program prints
+    pub(crate) exit_with_result: bool
+}
+
+impl Options {
+    pub(crate) fn parse(args: &[String]) -> Result<Options, Failure> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| match args.next() {
+                Some(value) => Ok(value.clone()),
+                None => Err((USAGE_ERROR, format!("'{}' needs a value after it", flag)))
+            };
+
+            match arg.as_str() {
+                "-o" | "--output" => options.output = Some(value(arg)?),
+                "-O" | "--optimize" => options.optimize = true,
+                "--listing" => options.listing = true,
+                "--exit-with-result" => options.exit_with_result = true,
+                "--trace" => options.trace = Some(value(arg)?),
+                "--fuel" => {
+                    let fuel = value(arg)?;
+                    options.fuel = Some(fuel.parse().map_err(|_| (USAGE_ERROR, format!("'{}' is not a valid amount of fuel", fuel)))?);
+                },
+                flag if flag.starts_with('-') && flag != "-" => return Err((USAGE_ERROR, format!("Unknown option '{}'", flag))),
+                input => {
+                    if options.input.is_some() {
+                        return Err((USAGE_ERROR, format!("Only one file can be given, but '{}' was also given", input)))
+                    }
+                    options.input = Some(input.to_string());
+                }
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn input(&self) -> Result<&str, Failure> {
+        match &self.input {
+            Some(input) => Ok(input),
+            None => Err((USAGE_ERROR, format!("A file is needed\n\n{}", HELP)))
+        }
+    }
+}
+
+//runs the command given on the command line, returning the code to exit with
+pub fn run(args: &[String]) -> i32 {
+    match execute(args) {
+        Ok(code) => code,
+        Err((code, message)) => {
+            eprintln!("error: {}", message);
+            code
+        }
+    }
+}
+
+fn execute(args: &[String]) -> Result<i32, Failure> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return Err((USAGE_ERROR, HELP.to_string()))
+    };
+
+    if matches!(command, "help" | "-h" | "--help") {
+        println!("{}", HELP);
+        return Ok(0)
+    }
+
+    if command == "repl" {
+        Repl::new().run();
+        return Ok(0)
+    }
+
+    let options = Options::parse(args)?;
+    match command {
+        "build" => build(&options),
+        "run" => {
+            let program = read(options.input()?)?;
+            run_program(program, &options)
+        },
+        "exec" => {
+            let mut compiler = Compiler::default();
+            compiler.set_optimize(options.optimize);
+            let program = compiler.build_file(options.input()?, false).map_err(compile_failure)?.bytes.clone();
+
+            print_warnings(&compiler);
+            run_program(program, &options)
+        },
+        "disasm" => {
+            let source = Disassembler::disassemble(&read(options.input()?)?).map_err(compile_failure)?;
+            match &options.output {
+                Some(output) => fs::write(output, source).map_err(|e| (IO_ERROR, format!("Cannot write '{}': {}", output, e)))?,
+                None => print!("{}", source)
+            }
+            Ok(0)
+        },
+        "test" => test(&options),
+        "watch" => {
+            Watcher::new(options.input()?, options.fuel.unwrap_or(DEFAULT_FUEL)).run();
+            Ok(0)
+        },
+        command => Err((USAGE_ERROR, format!("Unknown command '{}'\n\n{}", command, HELP)))
+    }
+}
+
+fn build(options: &Options) -> Result<i32, Failure> {
+    let input = options.input()?;
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(input).with_extension("binar").display().to_string()
+    };
+
+    let mut compiler = Compiler::new(output);
+    compiler.set_optimize(options.optimize);
+    compiler.set_listing(options.listing);
+    compiler.compile_file(input, false).map_err(compile_failure)?;
+
+    print_warnings(&compiler);
+    Ok(0)
+}
+
+fn test(options: &Options) -> Result<i32, Failure> {
+    let input = options.input()?;
+    let suite = TestSuite::build_file(input).map_err(compile_failure)?;
+    let results = suite.run(options.fuel.unwrap_or(DEFAULT_FUEL));
+
+    let mut failed = 0;
+    for result in &results {
+        match &result.failure {
+            None => println!("test {} ... ok", result.name),
+            Some((line, message)) => {
+                println!("test {} ... FAILED\n    {}:{}: {}", result.name, input, line, message);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{} tests, {} passed, {} failed", results.len(), results.len() - failed, failed);
+    Ok(if failed == 0 { 0 } else { TEST_FAILURE })
+}
+
+fn run_program(program: Vec<u8>, options: &Options) -> Result<i32, Failure> {
+    let mut vm = Machine::new();
+
+    if let Some(path) = &options.trace {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stderr())
+        }
+        else {
+            Box::new(BufWriter::new(fs::File::create(path).map_err(|e| (IO_ERROR, format!("Cannot write '{}': {}", path, e)))?))
+        };
+        vm.set_tracer(Box::new(JsonLinesTracer::new(writer)));
+    }
+
+    if !vm.load(program) {
+        return Err((RUNTIME_ERROR, "File is not a compiled Nariva program".into()))
+    }
+
+    let fuel = options.fuel.unwrap_or(u64::MAX);
+    let result = vm.resume_with_fuel(fuel);
+
+    //the trace is flushed as the tracer is dropped
+    drop(vm.take_tracer());
+
+    match result {
+        Ok(result) => {
+            //the result is only the exit code when asked for, as it could otherwise be mistaken for one of the error codes
+            if result != u64::MAX {
+                println!("Finished with {} on the stack", result);
+            }
+
+            match result {
+                u64::MAX => Ok(0),
+                result if options.exit_with_result => Ok(result as u8 as i32),
+                _ => Ok(0)
+            }
+        },
+        Err(e) => Err((RUNTIME_ERROR, describe(&e)))
+    }
+}
+
+//errors from running a program, in the words shown to the user
+pub fn describe(error: &VmError) -> String {
+    match error {
+        VmError::OutOfFuel(message) | VmError::OutOfMemory(message) | VmError::Fault(message) => message.clone(),
+        VmError::Trap { code, address, message } => {
+            let stopped = match *code {
+                ASSERT_CODE => format!("Assertion failed at address {}", address),
+                code => format!("Trap {} at address {}", code, address)
+            };
+            match message {
+                Some(message) => format!("{}: {}", stopped, message),
+                None => stopped
+            }
+        },
+        error => format!("{:?}", error)
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, Failure> {
+    fs::read(path).map_err(|e| (IO_ERROR, format!("Cannot read '{}': {}", path, e)))
+}
+
+fn compile_failure(error: CompError) -> Failure {
+    match error {
+        CompError::Io(message) => (IO_ERROR, message),
+        error => (COMPILE_ERROR, format!("{:?}", error))
+    }
+}
+
+fn print_warnings(compiler: &Compiler) {
+    for warning in compiler.get_warnings() {
+        eprintln!("warning: {:?}", warning);
+    }
+}
@@ -1,13 +1,26 @@
 extern crate virtual_machine;
 extern crate compiler;
 
+mod cli;
+mod repl;
+mod watch;
 
 use virtual_machine::vm::Machine;
 use compiler::compiler::Compiler;
+use compiler::dot::Dot;
+use compiler::object::ObjectFile;
+use compiler::linker::Linker;
 use std::fs;
 use std::io;
+use std::path::Path;
 
 fn main() {
+    //with arguments nariva runs a single command, otherwise it shows the interactive menu
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     /*
     let mut vm = Machine::new();
     let file_data = fs::read("nar files/fizzbuzz.nar").unwrap();
@@ -33,16 +46,13 @@ fn main() {
             .read_line(&mut debug_mode)
             .expect("Failed to read line");
 
-    let debug_mode: bool = match debug_mode.trim().parse().unwrap() {
-        'y' | 'Y' => {
-            println!("Debug mode activated...");
-            true
-        },
-        _ => {
-            println!("Debug mode deactivated...");
-            false
-        },
-    };
+    let debug_mode = matches!(debug_mode.trim(), "y" | "Y");
+    if debug_mode {
+        println!("Debug mode activated...");
+    }
+    else {
+        println!("Debug mode deactivated...");
+    }
 
 
 
@@ -52,18 +62,28 @@ What would you like to do?
     1) Compile a .nar file
     2) Run a .binar file
     3) Compile and run a .nar file
-    4) Exit program");
+    4) Export the control-flow graph of a .nar or .binar file
+    5) Compile a .nar file into a .obj object file
+    6) Link .obj object files into a .binar file
+    7) Start a REPL
+    8) Exit program");
 
         let mut input = String::new();
         io::stdin()
             .read_line(&mut input)
             .expect("Failed to read line");
 
-        let input: u64 = input.trim().parse().unwrap();
+        let input: u64 = match input.trim().parse() {
+            Ok(input) => input,
+            Err(_) => {
+                println!("\n{:?} is not one of the options.\nReloading...", input.trim());
+                continue
+            }
+        };
 
         match input {
             1 => {
-                let (file_name, file_data) = match get_file_data(false) {
+                let (file_name, _) = match get_file_data(false) {
                     Ok(data) => data,
                     Err(e) => {
                         println!("\nError in reading file: {}\nReloading...", e);
@@ -72,8 +92,13 @@ What would you like to do?
                 };
 
                 let mut comp = Compiler::new(format!("nar files/{}.binar", file_name));
-                match comp.compile(String::from_utf8_lossy(&file_data), debug_mode) {
-                    Ok(_) => println!("\nSuccessfuly compiled: {}.nar", file_name),
+                comp.set_listing(true);
+                match comp.compile_file(source_path(&file_name), debug_mode) {
+                    Ok(_) => {
+                        print_warnings(&comp);
+                        print_allocation(&comp);
+                        println!("\nSuccessfuly compiled: {} (listing in nar files/{}.lst)", source_path(&file_name), file_name)
+                    },
                     Err(e) => println!("\nError in compiling: {:?}.\nReloading...", e)
                 }
             },
@@ -89,11 +114,14 @@ What would you like to do?
 
                 let mut vm = Machine::new();
                 let be_bytes = vm.run(file_data, debug_mode).to_be_bytes();
-                println!("\nSuccessfuly ran: {}.nar. \nLast item on vm stack: {}", file_name, u64::from_be_bytes(be_bytes));
+                match vm.trap() {
+                    Some(trap) => println!("\nError in running {}.nar: {}", file_name, cli::describe(trap)),
+                    None => println!("\nSuccessfuly ran: {}.nar. \nLast item on vm stack: {}", file_name, u64::from_be_bytes(be_bytes))
+                }
             },
 
             3 => {
-                let (file_name, file_data) = match get_file_data(false) {
+                let (file_name, _) = match get_file_data(false) {
                     Ok(data) => data,
                     Err(e) => {
                         println!("\nError in reading file: {}\nReloading...", e);
@@ -102,8 +130,8 @@ What would you like to do?
                 };
 
                 let mut comp = Compiler::new(format!("nar files/{}.binar", file_name));
-                match comp.compile(String::from_utf8_lossy(&file_data), debug_mode) {
-                    Ok(_) => (),
+                match comp.compile_file(source_path(&file_name), debug_mode) {
+                    Ok(_) => print_warnings(&comp),
                     Err(e) => {
                         println!("\nError in compiling: {:?}.\nReloading...", e);
                         continue
@@ -112,10 +140,96 @@ What would you like to do?
 
                 let mut vm = Machine::new();
                 let be_bytes = vm.run(comp.get_output().clone(), debug_mode).to_be_bytes();
-                println!("\nSuccessfuly ran: {}.nar. \nLast item on vm stack: {}", file_name, u64::from_be_bytes(be_bytes));
+                match vm.trap() {
+                    Some(trap) => println!("\nError in running {}.nar: {}", file_name, cli::describe(trap)),
+                    None => println!("\nSuccessfuly ran: {}.nar. \nLast item on vm stack: {}", file_name, u64::from_be_bytes(be_bytes))
+                }
 
             }
 
+            4 => {
+                println!("\nIs the file compiled (y/n)?");
+                let mut compiled = String::new();
+                io::stdin()
+                    .read_line(&mut compiled)
+                    .expect("Failed to read line");
+                let compiled = matches!(compiled.trim(), "y" | "Y");
+
+                let (file_name, file_data) = match get_file_data(compiled) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        println!("\nError in reading file: {}\nReloading...", e);
+                        continue;
+                    }
+                };
+
+                let graph = if compiled {
+                    Dot::from_binary(&file_data)
+                }
+                else {
+                    Dot::from_file(source_path(&file_name))
+                };
+
+                match graph.map(|graph| fs::write(format!("nar files/{}.dot", file_name), graph)) {
+                    Ok(Ok(())) => println!("\nSuccessfuly wrote: {}.dot", file_name),
+                    Ok(Err(e)) => println!("\nError in writing file: {}\nReloading...", e),
+                    Err(e) => println!("\nError in reading program: {:?}.\nReloading...", e)
+                }
+            },
+
+            5 => {
+                let (file_name, file_data) = match get_file_data(false) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        println!("\nError in reading file: {}\nReloading...", e);
+                        continue;
+                    }
+                };
+
+                match ObjectFile::compile(String::from_utf8_lossy(&file_data)).map(|object| fs::write(format!("nar files/{}.obj", file_name), object.to_bytes())) {
+                    Ok(Ok(())) => println!("\nSuccessfuly compiled: {}.obj", file_name),
+                    Ok(Err(e)) => println!("\nError in writing file: {}\nReloading...", e),
+                    Err(e) => println!("\nError in compiling: {:?}.\nReloading...", e)
+                }
+            },
+
+            6 => {
+                println!("\nEnter the object file names, separated by spaces, with the one to start running from first:");
+                let mut file_names = String::new();
+                io::stdin()
+                    .read_line(&mut file_names)
+                    .expect("Failed to read line");
+
+                let mut objects = Vec::new();
+                for file_name in file_names.split_whitespace() {
+                    let object = fs::read(format!("nar files/{}.obj", file_name))
+                        .map_err(|e| e.to_string())
+                        .and_then(|data| ObjectFile::from_bytes(&data).map_err(|e| format!("{:?}", e)));
+
+                    match object {
+                        Ok(object) => objects.push(object),
+                        Err(e) => {
+                            println!("\nError in reading {}.obj: {}", file_name, e);
+                            break
+                        }
+                    }
+                }
+
+                let output_name = file_names.split_whitespace().next().unwrap_or_default();
+                if objects.is_empty() || objects.len() != file_names.split_whitespace().count() {
+                    println!("Reloading...");
+                    continue
+                }
+
+                match Linker::link(&objects).map(|binary| fs::write(format!("nar files/{}.binar", output_name), binary)) {
+                    Ok(Ok(())) => println!("\nSuccessfuly linked: {}.binar", output_name),
+                    Ok(Err(e)) => println!("\nError in writing file: {}\nReloading...", e),
+                    Err(e) => println!("\nError in linking: {:?}.\nReloading...", e)
+                }
+            },
+
+            7 => repl::Repl::new().run(),
+
             _ => {
                 println!("\nExiting...");
                 break
@@ -126,6 +240,32 @@ What would you like to do?
     }
 }
 
+//programs in the structured language are used when there isn't a .nar file with the same name
+fn source_path(file_name: &str) -> String {
+    let path = format!("nar files/{}.nar", file_name);
+    let structured = format!("nar files/{}.nrs", file_name);
+
+    if !Path::new(&path).exists() && Path::new(&structured).exists() {
+        structured
+    }
+    else {
+        path
+    }
+}
+
+fn print_warnings(comp: &Compiler) {
+    for warning in comp.get_warnings() {
+        println!("\nWarning: {:?}", warning);
+    }
+}
+
+//where each .var variable ended up, as a table
+fn print_allocation(comp: &Compiler) {
+    if !comp.get_allocation().variables.is_empty() {
+        print!("\n{}", comp.get_allocation());
+    }
+}
+
 fn get_file_data(binary: bool) -> Result<(String, Vec<u8>), String> {
     println!("\nEnter the file name:");
 
@@ -143,9 +283,165 @@ fn get_file_data(binary: bool) -> Result<(String, Vec<u8>), String> {
         }
     }
     else {
-        match fs::read(format!("nar files/{}.nar", file_name)) {
+        match fs::read(source_path(&file_name)) {
             Ok(data) => Ok((file_name, data)),
             Err(e) => Err(format!("{:?}\n{:?}", file_name, e.to_string()))
         }
     }
 }
+
+
+#[cfg(test)]
+mod interface_tests {
+    use crate::cli::{self, Options, COMPILE_ERROR, IO_ERROR, RUNTIME_ERROR, USAGE_ERROR};
+    use crate::repl::Repl;
+    use crate::watch::Watcher;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parsing_options() {
+        let options = Options::parse(&args(&["main.nar", "-O", "--fuel", "50", "--exit-with-result", "-o", "out.binar"])).unwrap();
+        assert_eq!(Options {
+            input: Some("main.nar".into()),
+            output: Some("out.binar".into()),
+            optimize: true,
+            fuel: Some(50),
+            exit_with_result: true,
+            ..Options::default()
+        }, options);
+
+        assert_eq!(Err((USAGE_ERROR, "'--fuel' needs a value after it".to_string())), Options::parse(&args(&["main.nar", "--fuel"])));
+        assert_eq!(Err((USAGE_ERROR, "'lots' is not a valid amount of fuel".to_string())), Options::parse(&args(&["--fuel", "lots"])));
+        assert_eq!(Err((USAGE_ERROR, "Unknown option '--fast'".to_string())), Options::parse(&args(&["--fast"])));
+        assert!(Options::parse(&args(&["a.nar", "b.nar"])).is_err());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let dir = std::env::temp_dir().join(format!("nariva_exit_codes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, source: &str| {
+            let path = dir.join(name);
+            fs::write(&path, source).unwrap();
+            path.display().to_string()
+        };
+
+        let result = file("result.nar", "PUSH 300\nHALT\n");
+        assert_eq!(0, cli::run(&args(&["exec", &result])));
+
+        //only the lowest 8 bits of the result fit in an exit code
+        assert_eq!(44, cli::run(&args(&["exec", &result, "--exit-with-result"])));
+        assert_eq!(0, cli::run(&args(&["exec", &file("empty.nar", "PUSH 1\nPOP\n"), "--exit-with-result"])));
+
+        assert_eq!(COMPILE_ERROR, cli::run(&args(&["exec", &file("bad.nar", "FOO 1\n")])));
+        assert_eq!(COMPILE_ERROR, cli::run(&args(&["exec", &file("number.nar", "PUSH 1.2.3\n")])));
+        assert_eq!(RUNTIME_ERROR, cli::run(&args(&["exec", &file("underflow.nar", "PUSH 1\nADDU\n")])));
+        assert_eq!(RUNTIME_ERROR, cli::run(&args(&["exec", &result, "--fuel", "0"])));
+        assert_eq!(IO_ERROR, cli::run(&args(&["exec", &dir.join("missing.nar").display().to_string()])));
+        assert_eq!(USAGE_ERROR, cli::run(&args(&["exec"])));
+        assert_eq!(USAGE_ERROR, cli::run(&args(&["launch"])));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn stack(repl: &Repl) -> String {
+        repl.state().lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn repl_state() {
+        let mut repl = Repl::new();
+        assert_eq!(Ok(()), repl.eval("PUSH 3\n"));
+        assert_eq!(Ok(()), repl.eval("PUSH 4\nADDU\n"));
+        assert_eq!("Stack: [7]", stack(&repl));
+
+        //the first variable is kept in the last register, and keeps its value between inputs
+        assert_eq!(Ok(()), repl.eval("count = 5\n"));
+        assert_eq!(Ok(()), repl.eval("count = count + 1\nLOAD count\n"));
+        assert_eq!("Stack: [7, 6]\nRegisters: [0, 0, 0, 0, 0, 0, 0, 6]\nFlag: None\n", repl.state());
+
+        repl.reset();
+        assert_eq!("Stack: []\nRegisters: [0, 0, 0, 0, 0, 0, 0, 0]\nFlag: None\n", repl.state());
+        assert!(repl.eval("LOAD count\n").unwrap_err().contains("isn't declared"));
+    }
+
+    #[test]
+    fn repl_functions() {
+        let mut repl = Repl::new();
+
+        //a HALT goes before the function, so the input stops there rather than running into the RETURN
+        assert_eq!(Ok(()), repl.eval("PUSH 4\ndouble:\nDUPLI\nADDU\nRETURN\n\n"));
+        assert_eq!("Stack: [4]", stack(&repl));
+
+        assert_eq!(Ok(()), repl.eval("CALL double\n"));
+        assert_eq!(Ok(()), repl.eval("CALL double\n"));
+        assert_eq!("Stack: [16]", stack(&repl));
+        assert!(repl.show().contains("Functions: double"));
+
+        repl.reset();
+        assert!(repl.eval("CALL double\n").is_err());
+        assert!(!repl.show().contains("Functions"));
+    }
+
+    #[test]
+    fn repl_recovers_from_faults() {
+        let mut repl = Repl::new();
+        assert_eq!(Ok(()), repl.eval("PUSH 2\n"));
+
+        //whatever ran before the fault is kept, and the next input runs as usual
+        assert_eq!(Err("Not enough numbers on the stack at address 36".to_string()), repl.eval("PUSH 5\nADDU\nADDU\n"));
+        assert_eq!("Stack: [7]", stack(&repl));
+
+        assert_eq!(Ok(()), repl.eval("PUSH 1\nADDU\n"));
+        assert_eq!("Stack: [8]", stack(&repl));
+
+        //input that doesn't compile leaves nothing behind
+        assert!(repl.eval("FOO 1\n").is_err());
+        assert_eq!(Ok(()), repl.eval("DUPLI\n"));
+        assert_eq!("Stack: [8, 8]", stack(&repl));
+    }
+
+    #[test]
+    fn output_diff() {
+        assert_eq!("Output unchanged\n", Watcher::diff("1\n2\n", "1\n2\n"));
+        assert_eq!("  1\n+ 2\n  3\n", Watcher::diff("1\n3\n", "1\n2\n3\n"));
+        assert_eq!("  1\n- 2\n  3\n", Watcher::diff("1\n2\n3\n", "1\n3\n"));
+        assert_eq!("  Fizz\n- 4\n+ 5\n  Buzz\n", Watcher::diff("Fizz\n4\nBuzz\n", "Fizz\n5\nBuzz\n"));
+        assert_eq!("+ 1\n", Watcher::diff("", "1\n"));
+        assert_eq!("- 1\n", Watcher::diff("1\n", ""));
+    }
+
+    #[test]
+    fn watching() {
+        let dir = std::env::temp_dir().join(format!("nariva_watching_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.nar");
+        let shared = dir.join("shared.nar");
+        fs::write(&main, ".include \"shared.nar\"\nPUSH 1\nPRINT 0\nCALL two\nHALT\n").unwrap();
+        fs::write(&shared, "two:\nPUSH 2\nPRINT 0\nRETURN\n").unwrap();
+
+        //both files are given a time well before the build, so they aren't mistaken for being saved during it
+        let touch = |path: &std::path::Path, seconds: u64| fs::File::options().write(true).open(path).unwrap().set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+        touch(&main, 1_000);
+        touch(&shared, 1_000);
+
+        let mut watcher = Watcher::new(&main, 1_000);
+        assert!(watcher.changed());
+        assert_eq!("1\n2\nFinished with an empty stack\n", watcher.rebuild());
+        assert!(!watcher.changed());
+
+        //an included file is watched too, and only the lines that changed are marked
+        fs::write(&shared, "two:\nPUSH 3\nPRINT 0\nRETURN\n").unwrap();
+        touch(&shared, 2_000);
+        assert!(watcher.changed());
+        assert_eq!("  1\n- 2\n+ 3\nFinished with an empty stack\n", watcher.rebuild());
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
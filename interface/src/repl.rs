@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use compiler::include::Includer;
+use compiler::lexer::Lexer;
+use compiler::linker::Linker;
+use compiler::object::ObjectFile;
+use compiler::structured::ast::Type;
+use compiler::token::Token;
+use virtual_machine::instruction::OpCode;
+use virtual_machine::vm::{HEADER, Machine};
+
+use crate::cli::describe;
+
+//most instructions a single input can run, so a loop that never ends gives the prompt back
+const FUEL: u64 = 10_000_000;
+
+//registers the vm has, which variables are given from the last one down
+const REGISTER_COUNT: usize = 8;
+
+const HELP: &str = "Type instructions to run them straight away, e.g. PUSH 3, and the stack, registers and flag are shown after each one.
+A line ending in ':' starts a function, which continues until an empty line, and can then be called by anything typed after it.
+Variables keep their value between inputs, each in its own register counting down from register 7.
+
+Commands:
+    :show           Show the stack, registers, flag and defined functions
+    :reset          Start again with an empty machine and no functions
+    :load <file>    Run a .nar file and the files it includes in the session, keeping the functions it defines
+    :help           Show this message
+    :quit           Leave the REPL";
+
+/*
+Keeps a live machine between inputs, so the stack, registers and memory carry on from one line to the next.
+Each input is compiled into an object file, with calls to functions defined by earlier inputs as imports, and linked after everything typed before it.
+As earlier code is never moved, the linked program only grows, and the machine runs just the part that was added.
+Variables are given a register the first time they are used rather than by the register allocator, so they stay in the same place from one input to the next.
+*/
+pub struct Repl {
+    machine: Machine,
+    objects: Vec<ObjectFile>,
+
+    //type of each variable declared so far, so later inputs can use it without declaring it again
+    variables: HashMap<String, Type>,
+
+    //variables in the order they were first used, the first being kept in the last register
+    registers: Vec<String>
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        let mut machine = Machine::new();
+        machine.load(HEADER.to_vec());
+
+        Repl { machine, objects: Vec::new(), variables: HashMap::new(), registers: Vec::new() }
+    }
+
+    //forgets everything typed so far, starting again with an empty machine
+    pub fn reset(&mut self) {
+        *self = Repl::new();
+    }
+
+    //reads inputs from stdin until :quit or the end of input
+    pub fn run(&mut self) {
+        println!("Nariva REPL, type :help for help");
+
+        while let Some(input) = Repl::read_input() {
+            let trimmed = input.trim();
+            if trimmed.is_empty() {
+                continue
+            }
+
+            let (command, argument) = trimmed.split_once(char::is_whitespace).map_or((trimmed, ""), |(command, argument)| (command, argument.trim()));
+            match command {
+                ":quit" | ":q" => break,
+                ":help" => println!("{}", HELP),
+                ":show" => print!("{}", self.show()),
+                ":reset" => {
+                    self.reset();
+                    println!("Reset");
+                },
+                ":load" => match self.load(argument) {
+                    Ok(()) => print!("{}", self.state()),
+                    Err(e) => println!("Error: {}", e)
+                },
+                command if command.starts_with(':') => println!("Unknown command '{}', type :help for help", command),
+                _ => self.report(&input)
+            }
+        }
+    }
+
+    //a line ending in ':' is a function label, so the lines after it are read until an empty one
+    fn read_input() -> Option<String> {
+        let mut input = String::new();
+        let mut prompt = "> ";
+
+        loop {
+            print!("{}", prompt);
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return if input.is_empty() { None } else { Some(input) }
+            }
+
+            let ends_input = prompt == "> " && !line.trim_end().ends_with(':') || prompt == "... " && line.trim().is_empty();
+            input.push_str(&line);
+            if ends_input {
+                return Some(input)
+            }
+            prompt = "... ";
+        }
+    }
+
+    fn report(&mut self, input: &str) {
+        match self.eval(input) {
+            Ok(()) => print!("{}", self.state()),
+            Err(e) => println!("Error: {}", e)
+        }
+    }
+
+    /*
+    Compiles input and runs it on the live machine.
+    Nothing is kept from input that doesn't compile or link, while input that fails part way through running keeps whatever it did before failing.
+    */
+    pub fn eval(&mut self, input: &str) -> Result<(), String> {
+        let mut variables = self.variables.clone();
+        let mut tokens = Lexer::lex_with_variables(input, &mut variables).map_err(|e| format!("{:?}", e))?;
+
+        //input that only declares variables has nothing to run
+        if tokens.is_empty() {
+            self.variables = variables;
+            return Ok(())
+        }
+
+        let registers = self.place_variables(&mut tokens, |_, name| name.to_string())?;
+
+        self.execute(tokens, registers, variables)
+    }
+
+    /*
+    Same as eval for a file along with the files it includes, found relative to it.
+    Variables of the file itself are shared with those of the same name typed at the prompt, while variables of included files belong to their own file.
+    */
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let mut source = Includer::lex_path(path).map_err(|e| format!("{:?}", e))?;
+        let registers = self.place_variables(&mut source.tokens, |index, name| match source.paths[source.files[index]].file_name() {
+            Some(file_name) if source.files[index] != 0 => format!("{} in '{}'", name, file_name.to_string_lossy()),
+            _ => name.to_string()
+        })?;
+
+        self.execute(source.tokens, registers, self.variables.clone())
+    }
+
+    //replaces variable names after LOAD and STORE with their registers, returning every variable used so far, named by label from the index of each name
+    fn place_variables<F: Fn(usize, &str) -> String>(&self, tokens: &mut [Token], label: F) -> Result<Vec<String>, String> {
+        let mut registers = self.registers.clone();
+
+        for index in 1..tokens.len() {
+            let name = match (&tokens[index - 1], &tokens[index]) {
+                (Token::OpCode(access), Token::OpCode(name)) if matches!(OpCode::from(access), OpCode::Load | OpCode::Store) => label(index, name),
+                _ => continue
+            };
+
+            let position = match registers.iter().position(|known| *known == name) {
+                Some(position) => position,
+                None => {
+                    registers.push(name);
+                    registers.len() - 1
+                }
+            };
+
+            if position >= REGISTER_COUNT {
+                return Err(format!("Variable '{}' doesn't fit, as the REPL only has room for {} variables", registers[position], REGISTER_COUNT))
+            }
+            tokens[index] = Token::NumU((REGISTER_COUNT - 1 - position) as u64);
+        }
+
+        Ok(registers)
+    }
+
+    //links tokens after the code of earlier inputs and runs them, keeping the variables they declare and the registers given to them once they link
+    fn execute(&mut self, mut tokens: Vec<Token>, registers: Vec<String>, variables: HashMap<String, Type>) -> Result<(), String> {
+        //the code before the first function is all that runs, so it stops before reaching one
+        if let Some(first) = tokens.iter().position(|token| matches!(token, Token::Func(_))) {
+            tokens.insert(first, Token::OpCode("HALT".into()));
+        }
+
+        let object = ObjectFile::from_tokens(tokens).map_err(|e| format!("{:?}", e))?;
+        self.objects.push(object);
+
+        let program = match Linker::link(&self.objects) {
+            Ok(program) => program,
+            Err(e) => {
+                self.objects.pop();
+                return Err(format!("{:?}", e))
+            }
+        };
+        self.registers = registers;
+        self.variables = variables;
+
+        //the new code starts where the program's code ended before it was added, and loading splits off the messages after it
+        let start = self.machine.program.len();
+        self.machine.load(program);
+        self.machine.program_address = start - 1;
+        self.machine.return_addresses.clear();
+
+        let mut used = 0;
+        while !self.machine.is_finished() && used < FUEL {
+            self.machine.execute_instruction();
+            used += 1;
+        }
+
+        match self.machine.trap() {
+            Some(trap) => Err(describe(trap)),
+            None if self.machine.is_finished() => Ok(()),
+            None => Err(format!("Stopped after running {} instructions, at address {}", FUEL, self.machine.program_address + 1))
+        }
+    }
+
+    //stack, registers and flag, shown after each input
+    pub fn state(&self) -> String {
+        format!("Stack: {:?}\nRegisters: {:?}\nFlag: {:?}\n", self.machine.stack, self.machine.registers, self.machine.flag())
+    }
+
+    pub fn show(&self) -> String {
+        let mut output = self.state();
+
+        if !self.machine.memory.is_empty() {
+            output.push_str(&format!("Memory: {:?}\n", self.machine.memory));
+        }
+
+        let functions: Vec<&str> = self.objects.iter().flat_map(|object| object.exports.iter().map(|(name, _)| name.as_str())).collect();
+        if !functions.is_empty() {
+            output.push_str(&format!("Functions: {}\n", functions.join(", ")));
+        }
+
+        output
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use compiler::compiler::Compiler;
+use virtual_machine::output::SharedBuffer;
+use virtual_machine::vm::Machine;
+
+use crate::cli::describe;
+
+//how often the files are checked for changes
+const POLL: Duration = Duration::from_millis(500);
+
+/*
+Recompiles and reruns a program whenever it or a file it includes is saved.
+Files are watched by polling their modification times, and each run is given limited fuel so a program stuck in a loop can be fixed and saved again.
+After the first run only the lines of output that changed are shown.
+*/
+pub struct Watcher {
+    path: PathBuf,
+    fuel: u64,
+
+    //every file the program was last compiled from, along with when it was modified
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+
+    //output of the last run that finished, which the next run is compared against
+    output: Option<String>
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(path: P, fuel: u64) -> Watcher {
+        Watcher { path: path.as_ref().to_path_buf(), fuel, files: Vec::new(), output: None }
+    }
+
+    //runs until the process is stopped
+    pub fn run(&mut self) {
+        println!("Watching {}, press Ctrl-C to stop", self.path.display());
+
+        loop {
+            if self.changed() {
+                println!("----------------------------------------------");
+                print!("{}", self.rebuild());
+            }
+
+            thread::sleep(POLL);
+        }
+    }
+
+    //whether any watched file has been modified, created or removed since it was last compiled
+    pub fn changed(&self) -> bool {
+        self.files.is_empty() || self.files.iter().any(|(path, modified)| Watcher::modified(path) != *modified)
+    }
+
+    //compiles and runs the program, returning what to show
+    pub fn rebuild(&mut self) -> String {
+        //times are taken before the files are read, so a save made while compiling is picked up by the next check rather than lost
+        let mut known: Vec<(PathBuf, Option<SystemTime>)> = self.files.iter().map(|(path, _)| (path.clone(), Watcher::modified(path))).collect();
+        if known.is_empty() {
+            known.push((self.path.clone(), Watcher::modified(&self.path)));
+        }
+        let started = SystemTime::now();
+
+        let mut compiler = Compiler::default();
+        let result = compiler.build_file(&self.path, false).cloned();
+
+        //a program that fails to compile may have lost an include, so the files from the last successful compile are still watched
+        self.files = match &result {
+            Ok(program) if !program.files.is_empty() => program.files.iter().map(|path| match known.iter().find(|(known, _)| known == path) {
+                Some(file) => file.clone(),
+                None => (path.clone(), Watcher::modified_before(path, started))
+            }).collect(),
+            _ => known
+        };
+
+        let program = match result {
+            Ok(program) => program,
+            Err(e) => return format!("Error in compiling: {:?}\n", e)
+        };
+
+        let mut report = String::new();
+        for warning in &program.warnings {
+            report.push_str(&format!("Warning: {:?}\n", warning));
+        }
+
+        let output = SharedBuffer::new();
+        let mut vm = Machine::new();
+        vm.set_output(Box::new(output.clone()));
+        if !vm.load(program.bytes) {
+            return format!("{}Error: compiled program has no Nariva header\n", report)
+        }
+
+        let fuel = self.fuel;
+        let result = vm.resume_with_fuel(fuel);
+        let printed = output.to_string_lossy();
+
+        match &self.output {
+            Some(previous) => report.push_str(&Watcher::diff(previous, &printed)),
+            None => report.push_str(&printed)
+        }
+
+        match result {
+            Ok(u64::MAX) => report.push_str("Finished with an empty stack\n"),
+            Ok(result) => report.push_str(&format!("Finished with {} on the stack\n", result)),
+            Err(e) => report.push_str(&format!("Error: {}\n", describe(&e)))
+        }
+
+        self.output = Some(printed);
+        report
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    //when a file first found by compiling was modified, or None if that could have been after compiling started so that it is rebuilt again
+    fn modified_before(path: &Path, started: SystemTime) -> Option<SystemTime> {
+        Watcher::modified(path).filter(|modified| *modified < started)
+    }
+
+    /*
+    Lines removed from the old output start with '-' and lines added in the new output start with '+', using the longest common subsequence of lines.
+    Nothing but a note is returned when the output hasn't changed.
+    */
+    pub fn diff(old: &str, new: &str) -> String {
+        if old == new {
+            return "Output unchanged\n".to_string()
+        }
+
+        let old: Vec<&str> = old.lines().collect();
+        let new: Vec<&str> = new.lines().collect();
+
+        //common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+        let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i][j] = if old[i] == new[j] {
+                    common[i + 1][j + 1] + 1
+                }
+                else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+
+        let mut output = String::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                output.push_str(&format!("  {}\n", old[i]));
+                i += 1;
+                j += 1;
+            }
+            else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+                output.push_str(&format!("- {}\n", old[i]));
+                i += 1;
+            }
+            else {
+                output.push_str(&format!("+ {}\n", new[j]));
+                j += 1;
+            }
+        }
+
+        output
+    }
+}
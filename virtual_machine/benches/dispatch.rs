@@ -0,0 +1,91 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::io;
+
+use virtual_machine::decode::DecodedProgram;
+use virtual_machine::instruction::OpCode;
+use virtual_machine::vm::{Machine, HEADER};
+
+fn op(program: &mut Vec<u8>, opcode: OpCode, operand: Option<i64>) {
+    program.push(opcode.into());
+    if let Some(operand) = operand {
+        program.extend_from_slice(&operand.to_be_bytes());
+    }
+}
+
+//counts register 0 down from the given number to zero
+fn countdown(iterations: i64) -> Vec<u8> {
+    let mut program = HEADER.to_vec();
+    op(&mut program, OpCode::Push, Some(iterations));
+    op(&mut program, OpCode::Store, Some(0));
+
+    op(&mut program, OpCode::Load, Some(0));
+    op(&mut program, OpCode::Push, Some(1));
+    op(&mut program, OpCode::SubU, None);
+    op(&mut program, OpCode::Dupli, None);
+    op(&mut program, OpCode::Store, Some(0));
+    op(&mut program, OpCode::Push, Some(0));
+    op(&mut program, OpCode::CMP, Some(0));
+    op(&mut program, OpCode::JNE, Some(-47));
+    program
+}
+
+//same loop as countdown but the decrement happens in a function
+fn call_countdown(iterations: i64) -> Vec<u8> {
+    let mut program = HEADER.to_vec();
+    op(&mut program, OpCode::Push, Some(iterations));
+    op(&mut program, OpCode::Store, Some(0));
+
+    op(&mut program, OpCode::Call, Some(0));
+    let call_operand = program.len() - 8;
+    op(&mut program, OpCode::Load, Some(0));
+    op(&mut program, OpCode::Push, Some(0));
+    op(&mut program, OpCode::CMP, Some(0));
+    op(&mut program, OpCode::JNE, Some(-36));
+    op(&mut program, OpCode::Halt, None);
+
+    let function = (program.len() - 1) as u64;
+    program[call_operand..call_operand + 8].copy_from_slice(&function.to_be_bytes());
+    op(&mut program, OpCode::Load, Some(0));
+    op(&mut program, OpCode::Push, Some(1));
+    op(&mut program, OpCode::SubU, None);
+    op(&mut program, OpCode::Store, Some(0));
+    op(&mut program, OpCode::Return, None);
+    program
+}
+
+type Builder = fn(i64) -> Vec<u8>;
+
+fn dispatch(c: &mut Criterion) {
+    let programs: [(&str, Builder); 2] = [("countdown", countdown), ("call_countdown", call_countdown)];
+
+    for (name, build) in programs.iter() {
+        let mut group = c.benchmark_group(*name);
+
+        for iterations in [1_000, 100_000].iter() {
+            let program = build(*iterations);
+            let decoded = DecodedProgram::decode(&program).unwrap();
+            assert_eq!(Machine::new().run(program.clone(), false), Machine::new().run_decoded(&decoded));
+
+            group.bench_with_input(BenchmarkId::new("bytes", iterations), &program, |b, program| {
+                b.iter(|| {
+                    let mut machine = Machine::new();
+                    machine.set_output(Box::new(io::sink()));
+                    machine.run(program.clone(), false)
+                })
+            });
+
+            group.bench_with_input(BenchmarkId::new("decoded", iterations), &decoded, |b, decoded| {
+                b.iter(|| {
+                    let mut machine = Machine::new();
+                    machine.set_output(Box::new(io::sink()));
+                    machine.run_decoded(decoded)
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+//how many times a conditional jump did and did not jump
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64
+}
+
+//execution counts per instruction address, along with the outcomes of conditional jumps
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    executed: HashMap<usize, u64>,
+    branches: HashMap<usize, BranchCoverage>
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    //branch is whether a conditional jump at the address was taken, or None for any other opcode
+    pub fn record(&mut self, address: usize, branch: Option<bool>) {
+        *self.executed.entry(address).or_insert(0) += 1;
+
+        match branch {
+            Some(true) => self.branches.entry(address).or_default().taken += 1,
+            Some(false) => self.branches.entry(address).or_default().not_taken += 1,
+            None => ()
+        }
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        *self.executed.get(&address).unwrap_or(&0)
+    }
+
+    pub fn branch(&self, address: usize) -> BranchCoverage {
+        self.branches.get(&address).copied().unwrap_or_default()
+    }
+
+    //addresses of every instruction that ran at least once, in order
+    pub fn executed_addresses(&self) -> Vec<usize> {
+        let mut addresses: Vec<usize> = self.executed.keys().copied().collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    //Adds the counts from another run, e.g. when several test programs exercise the same code
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.executed {
+            *self.executed.entry(*address).or_insert(0) += count;
+        }
+
+        for (address, branch) in &other.branches {
+            let entry = self.branches.entry(*address).or_default();
+            entry.taken += branch.taken;
+            entry.not_taken += branch.not_taken;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::error::VmError;
+use crate::reader::ByteReader;
+use crate::vm::HEADER;
+
+//Code at the very end of a program that has a data section. The numbers decode to "Nariva Data"
+pub const DATA_MARKER: [u8; 11] = [78, 97, 114, 105, 118, 97, 32, 68, 97, 116, 97];
+
+/*
+Messages given to ASSERT and TRAP instructions, which are only needed when a program stops on one of them.
+They are stored after the code so that addresses in the code don't change, laid out big endian as:
+    for each message: address of its instruction (u64), length (u64), UTF-8 bytes,
+    length of everything before this in the data section (u64),
+    DATA_MARKER.
+A program with no messages has no data section at all.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataSection {
+    pub messages: Vec<(usize, String)>
+}
+
+impl DataSection {
+    pub fn new() -> DataSection {
+        DataSection::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    //message of the instruction at address, if it was given one
+    pub fn message(&self, address: usize) -> Option<&str> {
+        self.messages.iter().find(|(at, _)| *at == address).map(|(_, message)| message.as_str())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.messages.is_empty() {
+            return Vec::new()
+        }
+
+        let mut output = Vec::new();
+        for (address, message) in &self.messages {
+            output.extend_from_slice(&(*address as u64).to_be_bytes());
+            output.extend_from_slice(&(message.len() as u64).to_be_bytes());
+            output.extend_from_slice(message.as_bytes());
+        }
+
+        output.extend_from_slice(&(output.len() as u64).to_be_bytes());
+        output.extend_from_slice(&DATA_MARKER);
+        output
+    }
+
+    /*
+    Where the code in a program ends along with its data section, which is empty if the program doesn't have one.
+    A data section that is cut short or has a message that isn't valid text is an error, rather than being run as code.
+    */
+    pub fn split(program: &[u8]) -> Result<(usize, DataSection), VmError> {
+        let trailer = DATA_MARKER.len() + 8;
+        if program.len() < trailer || !program.ends_with(&DATA_MARKER) {
+            return Ok((program.len(), DataSection::new()))
+        }
+
+        let len_start = program.len() - trailer;
+        let mut reader = ByteReader::new(&program[len_start..], "data section", VmError::InvalidProgram);
+        let len = reader.take_u64()? as usize;
+        if len > len_start.saturating_sub(HEADER.len()) {
+            return Err(VmError::InvalidProgram(format!("Data section is {} bytes long but the program only has {} bytes of code before it", len, len_start.saturating_sub(HEADER.len()))))
+        }
+
+        let end = len_start - len;
+        let mut reader = ByteReader::new(&program[end..len_start], "data section", VmError::InvalidProgram);
+        let mut messages = Vec::new();
+        while !reader.is_finished() {
+            let address = reader.take_u64()? as usize;
+            let message_len = reader.take_count(1)?;
+            let message = String::from_utf8(reader.take(message_len)?.to_vec()).map_err(|e| reader.error(e.to_string()))?;
+            messages.push((address, message));
+        }
+
+        Ok((end, DataSection { messages }))
+    }
+}
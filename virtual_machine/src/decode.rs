@@ -0,0 +1,127 @@
+use crate::data::DataSection;
+use crate::error::VmError;
+use crate::instruction::OpCode;
+use crate::vm::{Flow, Machine, HEADER, NO_RETURN};
+
+//an opcode along with its operand, with jumps and calls pointing straight at the index of the instruction they go to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub opcode: OpCode,
+    pub operand: u64,
+    pub target: usize
+}
+
+/*
+A program which has been split into instructions once when it is loaded,
+so running it doesn't need to convert bytes to opcodes or rebuild operands every cycle.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedProgram {
+    pub instructions: Vec<Instruction>,
+
+    //address of every instruction in the original program
+    pub addresses: Vec<usize>,
+
+    //messages for ASSERT and TRAP instructions
+    pub data: DataSection
+}
+
+impl DecodedProgram {
+    pub fn decode(program: &[u8]) -> Result<DecodedProgram, VmError> {
+        if program.len() <= HEADER.len() || program[0..HEADER.len()] != HEADER {
+            return Err(VmError::InvalidProgram("Program is not a nariva file".into()))
+        }
+
+        //messages after the code aren't instructions
+        let (end, data) = DataSection::split(program)?;
+        let program = &program[..end];
+
+        let mut instructions = Vec::new();
+        let mut addresses = Vec::new();
+        let mut address = HEADER.len();
+
+        while address < program.len() {
+            let opcode = OpCode::from(program[address]);
+            let mut operand = 0;
+
+            if opcode.operand_count() == 1 {
+                let bytes = match program.get(address + 1..address + 9) {
+                    Some(bytes) => bytes,
+                    None => return Err(VmError::InvalidProgram(format!("{:?} at address {} is missing its operand", opcode, address)))
+                };
+                operand = u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+            }
+
+            instructions.push(Instruction { opcode, operand, target: 0 });
+            addresses.push(address);
+            address += 1 + opcode.operand_count() * 8;
+        }
+
+        for index in 0..instructions.len() {
+            let instruction = instructions[index];
+
+            //jumps are relative to the jump opcode, calls give the address right before the function
+            let target_address = match instruction.opcode {
+                OpCode::JMP | OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL => {
+                    (addresses[index] as i64).checked_add(i64::from_be_bytes(instruction.operand.to_be_bytes()))
+                },
+                OpCode::Call => (instruction.operand as i64).checked_add(1),
+                _ => continue
+            };
+
+            //jumping to the very end of the program finishes it
+            let target = match target_address {
+                Some(target_address) if target_address == program.len() as i64 => Some(instructions.len()),
+                Some(target_address) if target_address >= 0 => addresses.binary_search(&(target_address as usize)).ok(),
+                _ => None
+            };
+
+            match target {
+                Some(target) => instructions[index].target = target,
+                None => return Err(VmError::InvalidProgram(format!("{:?} at address {} does not land on an instruction", instruction.opcode, addresses[index])))
+            }
+        }
+
+        Ok(DecodedProgram { instructions, addresses, data })
+    }
+}
+
+impl Machine {
+    /*
+    Runs a decoded program from the start, with the same results as run().
+    Return addresses are kept as instruction indices while running, and instructions are not traced, profiled, covered or recorded.
+    */
+    pub fn run_decoded(&mut self, program: &DecodedProgram) -> u64 {
+        let mut index = 0;
+        let mut return_indices = Vec::new();
+        self.data = program.data.clone();
+
+        while index < program.instructions.len() {
+            let instruction = &program.instructions[index];
+            index += 1;
+
+            match self.apply(instruction.opcode, instruction.operand) {
+                Flow::Next => (),
+                Flow::Halt => break,
+                flow @ (Flow::Trap(_) | Flow::OutOfMemory(_) | Flow::Fault(_)) => {
+                    self.stop(flow, program.addresses[index - 1]);
+                    break
+                },
+                Flow::Jump => index = instruction.target,
+                Flow::Call => {
+                    return_indices.push(index);
+                    index = instruction.target
+                },
+                Flow::Return => match return_indices.pop() {
+                    Some(return_index) => index = return_index,
+                    None => {
+                        self.stop(Flow::Fault(NO_RETURN), program.addresses[index - 1]);
+                        break
+                    }
+                }
+            }
+        }
+
+        self.stack.pop().unwrap_or(u64::MAX)
+    }
+}
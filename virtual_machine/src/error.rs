@@ -0,0 +1,25 @@
+#[derive(Debug, Clone, PartialEq)]
+
+//errors raised by the vm
+pub enum VmError {
+    InvalidSnapshot(String),
+    InvalidTrace(String),
+    InvalidRecording(String),
+    ReplayDiverged(String),
+    InvalidProgram(String),
+
+    //the program was stopped after running the number of instructions it was allowed
+    OutOfFuel(String),
+
+    //STOREM wrote past the most memory a program can use
+    OutOfMemory(String),
+
+    //the program did something the vm can't do, such as popping from an empty stack, at the address of that instruction
+    Fault(String),
+
+    //the program stopped itself with TRAP, or with an ASSERT that popped 0, at the address of that instruction
+    Trap { code: u64, address: usize, message: Option<String> },
+}
+
+//code of the error raised by a failed ASSERT, which TRAP can't be given
+pub const ASSERT_CODE: u64 = 0;
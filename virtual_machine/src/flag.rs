@@ -1,9 +1,53 @@
+use std::convert::TryFrom;
+
+use crate::instruction::OpCode;
+
 //When the vm is comparing two numbers it will use a flag as the result
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Flag {
     None,
     Equal,
     Less,
     Greater,
     Overflow,
-}
\ No newline at end of file
+}
+
+impl Flag {
+    //whether a conditional jump would be taken with this flag set, or None if the opcode is not a conditional jump
+    pub fn jump_taken(&self, opcode: OpCode) -> Option<bool> {
+        match opcode {
+            OpCode::JE => Some(*self == Flag::Equal),
+            OpCode::JNE => Some(*self == Flag::Greater || *self == Flag::Less),
+            OpCode::JG => Some(*self == Flag::Greater),
+            OpCode::JL => Some(*self == Flag::Less),
+            _ => None
+        }
+    }
+}
+
+impl From<Flag> for u8 {
+    fn from(f: Flag) -> u8 {
+        match f {
+            Flag::None => 0,
+            Flag::Equal => 1,
+            Flag::Less => 2,
+            Flag::Greater => 3,
+            Flag::Overflow => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for Flag {
+    type Error = u8;
+
+    fn try_from(f: u8) -> Result<Flag, u8> {
+        match f {
+            0 => Ok(Flag::None),
+            1 => Ok(Flag::Equal),
+            2 => Ok(Flag::Less),
+            3 => Ok(Flag::Greater),
+            4 => Ok(Flag::Overflow),
+            _ => Err(f)
+        }
+    }
+}
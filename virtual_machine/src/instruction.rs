@@ -1,6 +1,6 @@
 //these are the instructions for the vm
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OpCode {
     /*
     This represents an unknown opcode. 
@@ -66,6 +66,37 @@ pub enum OpCode {
     Load,
 
     PrintSTR,
+
+    //Converts the last number on the stack between whole numbers, intergers and floats
+    UtoF,
+    ItoF,
+    FtoU,
+    FtoI,
+
+    //Moves numbers between the stack and memory, which holds values that don't fit in the registers
+    StoreM,
+    LoadM,
+
+    //Stops the program with an error if the number popped from the stack is 0, or with the code after TRAP
+    Assert,
+    Trap,
+}
+
+impl OpCode {
+    pub fn is_conditional_jump(&self) -> bool {
+        matches!(self, OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL)
+    }
+
+    //number of 64 bit operands which follow the opcode in a program
+    pub fn operand_count(&self) -> usize {
+        match self {
+            OpCode::Push | OpCode::Shift | OpCode::CMP |
+            OpCode::JMP | OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL |
+            OpCode::Call | OpCode::Print | OpCode::Store | OpCode::Load | OpCode::PrintSTR |
+            OpCode::StoreM | OpCode::LoadM | OpCode::Trap => 1,
+            _ => 0
+        }
+    }
 }
 
 impl From<OpCode> for u8 {
@@ -121,7 +152,18 @@ impl From<OpCode> for u8 {
             OpCode::Store => 34,
             OpCode::Load => 35,
 
-            OpCode::PrintSTR => 36
+            OpCode::PrintSTR => 36,
+
+            OpCode::UtoF => 37,
+            OpCode::ItoF => 38,
+            OpCode::FtoU => 39,
+            OpCode::FtoI => 40,
+
+            OpCode::StoreM => 41,
+            OpCode::LoadM => 42,
+
+            OpCode::Assert => 43,
+            OpCode::Trap => 44
         }
     }
 }
@@ -177,6 +219,17 @@ impl From<u8> for OpCode {
             34 => OpCode::Store,
             35 => OpCode::Load,
             36 => OpCode::PrintSTR,
+
+            37 => OpCode::UtoF,
+            38 => OpCode::ItoF,
+            39 => OpCode::FtoU,
+            40 => OpCode::FtoI,
+
+            41 => OpCode::StoreM,
+            42 => OpCode::LoadM,
+
+            43 => OpCode::Assert,
+            44 => OpCode::Trap,
             
             _ => OpCode::Illegal,
         }
@@ -236,7 +289,19 @@ impl From<&String> for OpCode {
 
             "PrintSTR" | "PRINTSTR" => OpCode::PrintSTR,
 
-            "Illegal" | "ILLEGAL" | _ => OpCode::Illegal,
+            "UtoF" | "UTOF" => OpCode::UtoF,
+            "ItoF" | "ITOF" => OpCode::ItoF,
+            "FtoU" | "FTOU" => OpCode::FtoU,
+            "FtoI" | "FTOI" => OpCode::FtoI,
+
+            "StoreM" | "STOREM" => OpCode::StoreM,
+            "LoadM" | "LOADM" => OpCode::LoadM,
+
+            "Assert" | "ASSERT" => OpCode::Assert,
+            "Trap" | "TRAP" => OpCode::Trap,
+
+            //"Illegal" | "ILLEGAL" and every unknown word
+            _ => OpCode::Illegal,
         }
     }
 }
@@ -1,12 +1,31 @@
 pub mod vm;
 pub mod instruction;
 pub mod flag;
+pub mod error;
+pub mod output;
+pub mod snapshot;
+pub mod trace;
+pub mod profile;
+pub mod coverage;
+pub mod replay;
+pub mod decode;
+pub mod data;
+mod reader;
 
 
 #[cfg(test)]
 mod vm_tests {
     use crate::instruction::*;
     use crate::vm::*;
+    use crate::output::SharedBuffer;
+    use crate::error::{ASSERT_CODE, VmError};
+    use crate::data::DataSection;
+    use crate::trace::*;
+    use crate::replay::*;
+    use crate::decode::DecodedProgram;
+    use crate::flag::Flag;
+
+    const FIZZBUZZ: &[u8] = include_bytes!("../../nar files/fizzbuzz.binar");
 
     #[test]
     fn split_trait() {
@@ -95,6 +114,51 @@ mod vm_tests {
         assert_eq!(1.5, f64::from_be_bytes(machine.run(program, false).to_be_bytes()))
     }
     
+    #[test]
+    fn print_formats() {
+        //PRINT 2 shows the bits of the number as a decimal, the same way the F opcodes read them
+        let program = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,65,
+            OpCode::Print.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Push.into(), 191, 248, 0, 0, 0, 0, 0, 0,         //-1.5
+            OpCode::Print.into(), 0,0,0,0,0,0,0,2,
+            OpCode::Push.into(), 255,255,255,255,255,255,255,254,
+            OpCode::Print.into(), 0,0,0,0,0,0,0,1,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,42,
+            OpCode::Print.into(), 0,0,0,0,0,0,0,0,
+        ]].concat();
+
+        let output = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(output.clone()));
+        machine.run(program, false);
+        assert_eq!("A\n-1.5\n-2\n42\n", output.to_string_lossy());
+    }
+
+    #[test]
+    fn converting() {
+        let program: Vec<u8> = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,3,
+            OpCode::UtoF.into(),
+            OpCode::Push.into(), 191, 240, 0, 0, 0, 0, 0, 0,         //-1.0
+            OpCode::MulF.into(),
+            OpCode::FtoI.into(),
+        ]].concat();
+
+        let mut machine = Machine::new();
+        assert_eq!(-3, i64::from_be_bytes(machine.run(program, false).to_be_bytes()));
+
+        //negative floats saturate to 0 as whole numbers
+        let program: Vec<u8> = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 191, 240, 0, 0, 0, 0, 0, 0,
+            OpCode::FtoU.into(),
+        ]].concat();
+
+        let mut machine = Machine::new();
+        assert_eq!(0, machine.run(program, false));
+        assert_eq!(Flag::Overflow, machine.flag());
+    }
+
     #[test]
     fn bit_operations() {
         let program = [HEADER.to_vec(), vec![
@@ -121,21 +185,145 @@ mod vm_tests {
 
     #[test]
     fn jumping() {
+        //15 is greater than 10, so only JG is taken, and the PUSH 40 it lands on jumps back to HALT
         let program = [HEADER.to_vec(), vec![
             OpCode::Push.into(), 0,0,0,0,0,0,0,15,
             OpCode::Push.into(), 0,0,0,0,0,0,0,10,
             OpCode::CMP.into(), 0,0,0,0,0,0,0,0,
-            OpCode::JE.into(),
-            OpCode::JL.into(),
-            OpCode::JG.into(), 0,0,0,0,0,0,0,41,
+            OpCode::JE.into(), 0,0,0,0,0,0,0,46,
+            OpCode::JL.into(), 0,0,0,0,0,0,0,37,
+            OpCode::JG.into(), 0,0,0,0,0,0,0,10,
             OpCode::Halt.into(),
-            OpCode::Push.into(), 0,0,0,0,0,0,0,40, 
-            OpCode::JMP.into()
+            OpCode::Push.into(), 0,0,0,0,0,0,0,40,
+            OpCode::JMP.into(), 255,255,255,255,255,255,255,246,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,1,
+            OpCode::Halt.into()
             ]
         ].concat();
 
         let mut machine = Machine::new();
-        assert_eq!(u64::MAX, machine.run(program, true))
+        assert_eq!(40, machine.run(program, false))
+    }
+
+    #[test]
+    fn running_out_of_fuel() {
+        //jumping 0 bytes goes back to the same JMP forever
+        let program = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,7,
+            OpCode::JMP.into(), 0,0,0,0,0,0,0,0,
+        ]].concat();
+
+        let mut machine = Machine::new();
+        assert!(machine.load(program));
+        match machine.resume_with_fuel(10) {
+            Err(VmError::OutOfFuel(message)) => assert_eq!("Program ran out of fuel after 10 instructions, at address 26", message),
+            result => panic!("Expected to run out of fuel, got {:?}", result)
+        }
+
+        let program = [HEADER.to_vec(), vec![OpCode::Push.into(), 0,0,0,0,0,0,0,7]].concat();
+        let mut machine = Machine::new();
+        assert!(machine.load(program));
+        assert_eq!(Ok(7), machine.resume_with_fuel(1));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let program = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,7,
+            OpCode::StoreM.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,7,
+            OpCode::StoreM.into(), 255,255,255,255,255,255,255,255,
+        ]].concat();
+
+        //the write past the limit stops the program without growing memory
+        let error = VmError::OutOfMemory(format!("STOREM at address 44 writes to memory address {}, but memory only holds {} numbers", u64::MAX, MAX_MEMORY));
+        let mut machine = Machine::new();
+        assert!(machine.load(program.clone()));
+        assert_eq!(Err(error.clone()), machine.resume_with_fuel(100));
+        assert_eq!(vec![0, 0, 0, 7], machine.memory);
+
+        let mut decoded = Machine::new();
+        decoded.run_decoded(&DecodedProgram::decode(&program).unwrap());
+        assert_eq!(Some(&error), decoded.trap());
+    }
+
+    #[test]
+    fn faults() {
+        //programs that do something impossible stop with an error instead of panicking
+        let fault = |program: Vec<u8>| {
+            let mut machine = Machine::new();
+            machine.set_output(Box::new(SharedBuffer::new()));
+            assert!(machine.load([HEADER.to_vec(), program.clone()].concat()));
+            let result = machine.resume_with_fuel(100);
+
+            let decoded = DecodedProgram::decode(&[HEADER.to_vec(), program].concat()).unwrap();
+            let mut machine = Machine::new();
+            machine.set_output(Box::new(SharedBuffer::new()));
+            machine.run_decoded(&decoded);
+            assert_eq!(result.as_ref().err(), machine.trap());
+            result
+        };
+
+        assert_eq!(Err(VmError::Fault("Not enough numbers on the stack at address 26".into())), fault(vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,1,
+            OpCode::AddU.into()
+        ]));
+        assert_eq!(Err(VmError::Fault("Division by 0 at address 35".into())), fault(vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,1,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,0,
+            OpCode::ModU.into()
+        ]));
+        assert_eq!(Err(VmError::Fault("Register that doesn't exist at address 17".into())), fault(vec![OpCode::Load.into(), 0,0,0,0,0,0,0,8]));
+
+        //the remainder of the smallest integer divided by -1 overflows like DIVI, rather than stopping the program
+        let program = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 128,0,0,0,0,0,0,0,
+            OpCode::Push.into(), 255,255,255,255,255,255,255,255,
+            OpCode::ModI.into()
+        ]].concat();
+        let mut machine = Machine::new();
+        assert_eq!(0, machine.run(program, false));
+        assert_eq!(Flag::Overflow, machine.flag());
+        assert_eq!(Err(VmError::Fault("RETURN with no function to return to at address 17".into())), fault(vec![OpCode::Return.into()]));
+    }
+
+    #[test]
+    fn trapping() {
+        let data = DataSection { messages: vec![(27, "bad state".into())] };
+        let program = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,1,
+            OpCode::Assert.into(),
+            OpCode::Trap.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,7,
+        ], data.to_bytes()].concat();
+
+        let trap = VmError::Trap { code: 3, address: 27, message: Some("bad state".into()) };
+        let mut machine = Machine::new();
+        assert!(machine.load(program.clone()));
+        assert_eq!(data, machine.data);
+        assert_eq!(Err(trap), machine.resume_with_fuel(100));
+
+        //the decoded program stops at the same instruction, without running past it
+        let mut decoded = Machine::new();
+        assert_eq!(u64::MAX, decoded.run_decoded(&DecodedProgram::decode(&program).unwrap()));
+        assert_eq!(Some(&VmError::Trap { code: 3, address: 27, message: Some("bad state".into()) }), decoded.trap());
+
+        //an ASSERT that pops 0 traps with ASSERT_CODE and no message
+        let program = [HEADER.to_vec(), vec![OpCode::Push.into(), 0,0,0,0,0,0,0,0, OpCode::Assert.into()]].concat();
+        let mut machine = Machine::new();
+        assert!(machine.load(program));
+        assert_eq!(Err(VmError::Trap { code: ASSERT_CODE, address: 26, message: None }), machine.resume_with_fuel(100));
+
+        //as does one with nothing to pop
+        let mut machine = Machine::new();
+        assert!(machine.load([HEADER.to_vec(), vec![OpCode::Assert.into()]].concat()));
+        assert_eq!(Err(VmError::Trap { code: ASSERT_CODE, address: 17, message: None }), machine.resume_with_fuel(100));
+
+        //a data section with a message longer than itself isn't run as code
+        let mut broken = [HEADER.to_vec(), vec![OpCode::Halt.into()], data.to_bytes()].concat();
+        broken[18 + 15] = 200;
+        assert!(!Machine::new().load(broken.clone()));
+        assert!(DecodedProgram::decode(&broken).is_err());
     }
 
     #[test]
@@ -171,19 +359,235 @@ mod vm_tests {
         assert_eq!(10, machine.run(program, false))
     }
 
+    #[test]
+    fn snapshot_round_trip() {
+        let expected = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(expected.clone()));
+        let expected_result = machine.run(FIZZBUZZ.to_vec(), false);
+
+        //stops part of the way through, in the middle of a function call
+        let first_half = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(first_half.clone()));
+        assert!(machine.load(FIZZBUZZ.to_vec()));
+        while machine.return_addresses.is_empty() || machine.registers[1] == 0 {
+            machine.execute_instruction();
+        }
+        let snapshot = machine.snapshot();
+
+        let second_half = SharedBuffer::new();
+        let mut resumed = Machine::new();
+        resumed.set_output(Box::new(second_half.clone()));
+        resumed.restore(&snapshot).unwrap();
+
+        assert_eq!(snapshot, resumed.snapshot());
+        assert_eq!(expected_result, resumed.resume());
+        assert_eq!(expected.contents(), [first_half.contents(), second_half.contents()].concat());
+        assert!(!first_half.contents().is_empty() && !second_half.contents().is_empty());
+    }
+
+    #[test]
+    fn invalid_snapshot() {
+        let mut machine = Machine::new();
+        machine.load(FIZZBUZZ.to_vec());
+        let mut snapshot = machine.snapshot();
+
+        assert_eq!(Err(VmError::InvalidSnapshot("Snapshot ended unexpectedly".into())), Machine::new().restore(&snapshot[..snapshot.len() - 1]));
+
+        snapshot[15] = 255;
+        assert_eq!(Err(VmError::InvalidSnapshot("Unsupported snapshot version 65281".into())), Machine::new().restore(&snapshot));
+
+        assert_eq!(Err(VmError::InvalidSnapshot("Data is not a nariva snapshot".into())), Machine::new().restore(FIZZBUZZ));
+    }
+
+    #[test]
+    fn tracing() {
+        let program: Vec<u8> = [HEADER.to_vec(), vec![
+            OpCode::Push.into(), 0,0,0,0,0,0,0,6,
+            OpCode::Store.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Load.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Dupli.into(),
+            OpCode::CMP.into(), 0,0,0,0,0,0,0,0,
+        ]].concat();
+
+        let json = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_tracer(Box::new(JsonLinesTracer::new(json.clone())));
+        machine.run(program.clone(), false);
+
+        let lines: Vec<String> = json.to_string_lossy().lines().map(|l| l.to_string()).collect();
+        assert_eq!(vec![
+            r#"{"address":17,"opcode":"Push","operands":[6],"stack_before":[],"stack_after":[6],"registers_written":[],"flag":"None"}"#,
+            r#"{"address":26,"opcode":"Store","operands":[3],"stack_before":[6],"stack_after":[],"registers_written":[{"register":3,"value":6}],"flag":"None"}"#,
+            r#"{"address":35,"opcode":"Load","operands":[3],"stack_before":[],"stack_after":[6],"registers_written":[],"flag":"None"}"#,
+            r#"{"address":44,"opcode":"Dupli","operands":[],"stack_before":[6],"stack_after":[6,6],"registers_written":[],"flag":"None"}"#,
+            r#"{"address":45,"opcode":"CMP","operands":[0],"stack_before":[6,6],"stack_after":[],"registers_written":[],"flag":"Equal"}"#,
+        ], lines);
+
+        let binary = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_tracer(Box::new(BinaryTracer::new(binary.clone())));
+        machine.run(program, false);
+
+        let events = read_binary_trace(&binary.contents()).unwrap();
+        assert_eq!(5, events.len());
+        assert_eq!(TraceEvent {
+            address: 26,
+            opcode: OpCode::Store,
+            operands: vec![3],
+            stack_before: vec![6],
+            stack_after: vec![],
+            registers_written: vec![(3, 6)],
+            flag: crate::flag::Flag::None
+        }, events[1]);
+    }
+
+    #[test]
+    fn profiling() {
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(SharedBuffer::new()));
+        machine.enable_profiling();
+        machine.run(FIZZBUZZ.to_vec(), false);
+
+        let profiler = machine.take_profiler().unwrap();
+        let functions = profiler.functions();
+
+        //"increment" starts at code byte 114
+        let increment = &functions[&(HEADER.len() + 114)];
+        assert_eq!(20, increment.calls);
+        assert_eq!(60, increment.exclusive);
+        assert_eq!(60, increment.inclusive);
+
+        let main = &functions[&HEADER.len()];
+        assert_eq!(profiler.total(), main.inclusive);
+        assert_eq!(profiler.total(), functions.values().map(|f| f.exclusive).sum::<u64>());
+        assert_eq!(20, profiler.address_count(HEADER.len() + 114));
+        assert_eq!(20, profiler.opcode_count(OpCode::AddU));
+
+        let mut names = std::collections::HashMap::new();
+        names.insert(HEADER.len() + 114, "increment".to_string());
+        let mut profiler = profiler;
+        profiler.set_function_names(names);
+
+        assert!(profiler.folded_stacks().lines().any(|l| l == "main;increment 60"));
+        assert!(profiler.report().starts_with("Function"));
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(SharedBuffer::new()));
+        machine.start_recording();
+        machine.load(FIZZBUZZ.to_vec());
+        assert_eq!(Ok(5), machine.nondeterministic_input(InputKind::Random, || 5));
+        let result = machine.resume();
+        let recording = Recording::from_bytes(&machine.stop_recording().unwrap().to_bytes()).unwrap();
+
+        assert!(!recording.checkpoints.is_empty());
+        assert_eq!(vec![RecordedInput { step: 0, kind: InputKind::Random, value: 5 }], recording.inputs);
+
+        let mut replay = Machine::new();
+        replay.set_output(Box::new(SharedBuffer::new()));
+        replay.start_replay(recording.clone());
+        replay.load(FIZZBUZZ.to_vec());
+        assert_eq!(Ok(5), replay.nondeterministic_input(InputKind::Random, || 9));
+        assert_eq!(result, replay.resume());
+        assert_eq!(Ok(()), replay.finish_replay());
+
+        //the loop limit of 20 is changed to 30
+        let mut changed = FIZZBUZZ.to_vec();
+        changed[HEADER.len() + 8] = 30;
+        assert_eq!(Err(VmError::ReplayDiverged("Replay diverged: the program being run is not the one that was recorded".into())), replay_error(changed, recording.clone()));
+
+        //checkpoints are taken at every taken branch, so the first comes well before CHECKPOINT_INTERVAL
+        assert!(recording.checkpoints[0].step < CHECKPOINT_INTERVAL);
+        let step = recording.checkpoints[1].step;
+
+        let mut tampered = recording.clone();
+        tampered.checkpoints[1].address += 1;
+        tampered.checkpoints[1].flag = Flag::Overflow;
+        assert_eq!(Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: expected address {} but reached {}, expected flag Overflow but found {:?}",
+            step, recording.checkpoints[1].address + 1, recording.checkpoints[1].address, recording.checkpoints[1].flag))), replay_error(FIZZBUZZ.to_vec(), tampered));
+
+        let mut tampered = recording.clone();
+        tampered.checkpoints[1].memory += 1;
+        tampered.checkpoints[1].stack += 1;
+        assert_eq!(Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: the stack differs, memory differs", step))), replay_error(FIZZBUZZ.to_vec(), tampered));
+
+        let mut tampered = recording.clone();
+        tampered.checkpoints[1].path += 1;
+        assert_eq!(Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: a different path was taken to get there", step))), replay_error(FIZZBUZZ.to_vec(), tampered));
+
+        //a register that differs is noticed at the first checkpoint, even though the path is the same
+        let mut replay = Machine::new();
+        replay.set_output(Box::new(SharedBuffer::new()));
+        replay.start_replay(recording.clone());
+        replay.load(FIZZBUZZ.to_vec());
+        replay.registers[7] = 1;
+        assert_eq!(Ok(5), replay.nondeterministic_input(InputKind::Random, || 9));
+        assert_eq!(Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: the registers differ", recording.checkpoints[0].step))), replay.resume_with_fuel(u64::MAX));
+
+        //asking for an input the recording doesn't have
+        let mut replay = Machine::new();
+        replay.start_replay(recording);
+        replay.load(FIZZBUZZ.to_vec());
+        assert_eq!(Err(VmError::ReplayDiverged("Replay diverged at step 0: Time input requested but the recording has Random input at step 0".into())), replay.nondeterministic_input(InputKind::Time, || 9));
+    }
+
+    fn replay_error(program: Vec<u8>, recording: Recording) -> Result<u64, VmError> {
+        let mut replay = Machine::new();
+        replay.set_output(Box::new(SharedBuffer::new()));
+        replay.start_replay(recording);
+        assert!(replay.load(program));
+        let result = replay.resume_with_fuel(u64::MAX);
+        assert_eq!(None, replay.trap());
+        result
+    }
+
+    #[test]
+    fn decoded_program() {
+        let output = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(output.clone()));
+        let result = machine.run(FIZZBUZZ.to_vec(), false);
+
+        let decoded = DecodedProgram::decode(FIZZBUZZ).unwrap();
+        assert_eq!(HEADER.len(), decoded.addresses[0]);
+
+        //"JMP -9" at the end of main goes back to "CALL increment"
+        assert_eq!(OpCode::JMP, decoded.instructions[12].opcode);
+        assert_eq!(3, decoded.instructions[12].target);
+
+        let decoded_output = SharedBuffer::new();
+        let mut machine = Machine::new();
+        machine.set_output(Box::new(decoded_output.clone()));
+        assert_eq!(result, machine.run_decoded(&decoded));
+        assert_eq!(output.contents(), decoded_output.contents());
+
+        let program = [HEADER.to_vec(), vec![OpCode::JMP.into(), 0,0,0,0,0,0,0,4]].concat();
+        assert_eq!(Err(VmError::InvalidProgram("JMP at address 17 does not land on an instruction".into())), DecodedProgram::decode(&program));
+
+        let program = [HEADER.to_vec(), vec![OpCode::Push.into(), 0,0,0]].concat();
+        assert_eq!(Err(VmError::InvalidProgram("Push at address 17 is missing its operand".into())), DecodedProgram::decode(&program));
+    }
+
+    #[test]
     fn neg_jump() {
+        //counts down from 3, jumping back 29 bytes to the PUSH 1 until the count reaches 0
         let program = [HEADER.to_vec(), vec![
-            OpCode::Push.into(), 0,0,0,0,0,0,0,10,
-            OpCode::Push.into(), 0,0,0,0,0,0,0,5,
-            OpCode::CMP.into(),
-            OpCode::JNE.into(), 2,
-            OpCode::Halt.into(),
-            OpCode::Push.into(), 0,0,0,0,0,0,0,5,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,3,
+            OpCode::Push.into(), 0,0,0,0,0,0,0,1,
+            OpCode::SubU.into(),
             OpCode::Dupli.into(),
-            OpCode::CMP.into(),
-            OpCode::JE.into(), 255, 255, 255, 255, 255, 255, 255, 252,
-            OpCode::Print.into(), 0,0,0,0,0,0,0,3
+            OpCode::Push.into(), 0,0,0,0,0,0,0,0,
+            OpCode::CMP.into(), 0,0,0,0,0,0,0,0,
+            OpCode::JNE.into(), 255,255,255,255,255,255,255,227,
         ]].concat();
+
+        let mut machine = Machine::new();
+        assert_eq!(0, machine.run(program.clone(), false));
+        assert_eq!(0, Machine::new().run_decoded(&DecodedProgram::decode(&program).unwrap()));
     }
     
 }
\ No newline at end of file
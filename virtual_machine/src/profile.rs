@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::instruction::OpCode;
+use crate::vm::HEADER;
+
+//instruction counts attributed to a single function
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub calls: u64,
+
+    //instructions executed while the function, or anything it called, was running
+    pub inclusive: u64,
+
+    //instructions executed within the body of the function itself
+    pub exclusive: u64
+}
+
+/*
+Counts executed instructions per address and per opcode.
+Functions are identified by the address of their first instruction, with the start of the program treated as "main".
+Only exclusive counts are recorded for each distinct call stack, everything else is derived from them when reporting.
+*/
+pub struct Profiler {
+    addresses: HashMap<usize, (OpCode, u64)>,
+    opcodes: HashMap<OpCode, u64>,
+    calls: HashMap<usize, u64>,
+    stacks: HashMap<Vec<usize>, u64>,
+    call_stack: Vec<usize>,
+    names: HashMap<usize, String>
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            addresses: HashMap::new(),
+            opcodes: HashMap::new(),
+            calls: HashMap::new(),
+            stacks: HashMap::new(),
+            call_stack: vec![HEADER.len()],
+            names: HashMap::new()
+        }
+    }
+
+    //Names functions in reports, keyed by the address of their first instruction
+    pub fn set_function_names(&mut self, names: HashMap<usize, String>) {
+        self.names = names
+    }
+
+    pub fn record(&mut self, address: usize, opcode: OpCode) {
+        self.addresses.entry(address).or_insert((opcode, 0)).1 += 1;
+        *self.opcodes.entry(opcode).or_insert(0) += 1;
+
+        match self.stacks.get_mut(&self.call_stack) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.call_stack.clone(), 1);
+            }
+        }
+    }
+
+    pub fn enter(&mut self, function_address: usize) {
+        *self.calls.entry(function_address).or_insert(0) += 1;
+        self.call_stack.push(function_address)
+    }
+
+    pub fn exit(&mut self) {
+        //the start of the program is never left, even if a RETURN is reached without a CALL
+        if self.call_stack.len() > 1 {
+            self.call_stack.pop();
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.opcodes.values().sum()
+    }
+
+    pub fn address_count(&self, address: usize) -> u64 {
+        self.addresses.get(&address).map_or(0, |(_, count)| *count)
+    }
+
+    pub fn opcode_count(&self, opcode: OpCode) -> u64 {
+        *self.opcodes.get(&opcode).unwrap_or(&0)
+    }
+
+    //Counts for every function which was running at some point, keyed by function address
+    pub fn functions(&self) -> HashMap<usize, FunctionProfile> {
+        let mut functions: HashMap<usize, FunctionProfile> = HashMap::new();
+
+        for (stack, count) in &self.stacks {
+            functions.entry(*stack.last().unwrap()).or_default().exclusive += count;
+
+            //recursive functions are only counted once per stack
+            let mut seen = Vec::new();
+            for function in stack {
+                if !seen.contains(function) {
+                    functions.entry(*function).or_default().inclusive += count;
+                    seen.push(*function);
+                }
+            }
+        }
+
+        for (function, calls) in &self.calls {
+            functions.entry(*function).or_default().calls = *calls;
+        }
+
+        functions
+    }
+
+    pub fn function_name(&self, function_address: usize) -> String {
+        match self.names.get(&function_address) {
+            Some(name) => name.clone(),
+            None if function_address == HEADER.len() => "main".to_string(),
+            None => format!("fn@{}", function_address)
+        }
+    }
+
+    //Tables of functions, addresses and opcodes, each sorted from most to least executed instructions
+    pub fn report(&self) -> String {
+        let total = self.total().max(1) as f64;
+        let mut output = String::new();
+
+        let mut functions: Vec<(usize, FunctionProfile)> = self.functions().into_iter().collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(b.1.inclusive.cmp(&a.1.inclusive)).then(a.0.cmp(&b.0)));
+
+        output.push_str(&format!("{:<24} {:>10} {:>12} {:>12} {:>8}\n", "Function", "Calls", "Inclusive", "Exclusive", "Excl %"));
+        for (function, profile) in functions {
+            output.push_str(&format!("{:<24} {:>10} {:>12} {:>12} {:>7.2}%\n",
+                self.function_name(function), profile.calls, profile.inclusive, profile.exclusive, profile.exclusive as f64 * 100.0 / total));
+        }
+
+        let mut addresses: Vec<(&usize, &(OpCode, u64))> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| (b.1).1.cmp(&(a.1).1).then(a.0.cmp(b.0)));
+
+        output.push_str(&format!("\n{:<10} {:<10} {:>12} {:>8}\n", "Address", "Opcode", "Count", "%"));
+        for (address, (opcode, count)) in addresses {
+            output.push_str(&format!("{:<10} {:<10} {:>12} {:>7.2}%\n", address, format!("{:?}", opcode), count, *count as f64 * 100.0 / total));
+        }
+
+        let mut opcodes: Vec<(&OpCode, &u64)> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(u8::from(*a.0).cmp(&u8::from(*b.0))));
+
+        output.push_str(&format!("\n{:<10} {:>12} {:>8}\n", "Opcode", "Count", "%"));
+        for (opcode, count) in opcodes {
+            output.push_str(&format!("{:<10} {:>12} {:>7.2}%\n", format!("{:?}", opcode), count, *count as f64 * 100.0 / total));
+        }
+
+        output
+    }
+
+    //One "caller;callee count" line per distinct call stack, as read by flamegraph tools
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = self.stacks.iter().map(|(stack, count)| {
+            let names: Vec<String> = stack.iter().map(|f| self.function_name(*f)).collect();
+            format!("{} {}", names.join(";"), count)
+        }).collect();
+        lines.sort();
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::error::VmError;
+
+//reads big endian fields out of encoded vm data, failing instead of panicking when the data is cut short
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    index: usize,
+
+    //wraps messages in the error variant for the kind of data being read
+    error: fn(String) -> VmError,
+    name: &'static str
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8], name: &'static str, error: fn(String) -> VmError) -> ByteReader<'a> {
+        ByteReader { data, index: 0, error, name }
+    }
+
+    pub(crate) fn error(&self, message: String) -> VmError {
+        (self.error)(message)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.index >= self.data.len()
+    }
+
+    //checks the header and version at the start of the data
+    pub(crate) fn expect_header(&mut self, header: &[u8], version: u16) -> Result<(), VmError> {
+        if self.take(header.len())? != header {
+            return Err(self.error(format!("Data is not a nariva {}", self.name)))
+        }
+
+        let found = u16::from_be_bytes([self.take_u8()?, self.take_u8()?]);
+        if found != version {
+            return Err(self.error(format!("Unsupported {} version {}", self.name, found)))
+        }
+
+        Ok(())
+    }
+
+    fn cut_short(&self) -> VmError {
+        let name = self.name[..1].to_uppercase() + &self.name[1..];
+        self.error(format!("{} ended unexpectedly", name))
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], VmError> {
+        if self.data.len() - self.index < len {
+            return Err(self.cut_short())
+        }
+
+        self.index += len;
+        Ok(&self.data[self.index - len..self.index])
+    }
+
+    pub(crate) fn take_u8(&mut self) -> Result<u8, VmError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn take_u64(&mut self) -> Result<u64, VmError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]))
+    }
+
+    //reads a u64 count of items, checked against the remaining data so a corrupt count cannot trigger a huge allocation
+    pub(crate) fn take_count(&mut self, item_size: usize) -> Result<usize, VmError> {
+        let count = self.take_u64()?;
+        if count > ((self.data.len() - self.index) / item_size.max(1)) as u64 {
+            return Err(self.cut_short())
+        }
+
+        Ok(count as usize)
+    }
+
+    pub(crate) fn take_nums(&mut self, count: usize) -> Result<Vec<u64>, VmError> {
+        (0..count).map(|_| self.take_u64()).collect()
+    }
+}
@@ -0,0 +1,329 @@
+use std::convert::TryFrom;
+
+use crate::error::VmError;
+use crate::flag::Flag;
+use crate::instruction::OpCode;
+use crate::reader::ByteReader;
+
+//Code at the start of all saved recordings. The numbers decode to "Nariva Recording"
+pub const RECORDING_HEADER: [u8; 16] = [78, 97, 114, 105, 118, 97, 32, 82, 101, 99, 111, 114, 100, 105, 110, 103];
+
+pub const RECORDING_VERSION: u16 = 2;
+
+//most instructions executed between checks that a replay is still following its recording, as every taken branch and input read is checked too
+pub const CHECKPOINT_INTERVAL: u64 = 256;
+
+//sources of values which can differ between two runs of the same program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputKind {
+    Input,
+    Host,
+    Time,
+    Random,
+}
+
+impl From<InputKind> for u8 {
+    fn from(k: InputKind) -> u8 {
+        match k {
+            InputKind::Input => 0,
+            InputKind::Host => 1,
+            InputKind::Time => 2,
+            InputKind::Random => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for InputKind {
+    type Error = u8;
+
+    fn try_from(k: u8) -> Result<InputKind, u8> {
+        match k {
+            0 => Ok(InputKind::Input),
+            1 => Ok(InputKind::Host),
+            2 => Ok(InputKind::Time),
+            3 => Ok(InputKind::Random),
+            _ => Err(k)
+        }
+    }
+}
+
+//a value given to the vm, along with how many instructions had been executed when it was asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedInput {
+    pub step: u64,
+    pub kind: InputKind,
+    pub value: u64
+}
+
+//summary of the vm's state after a number of instructions, used to notice when a replay goes its own way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    pub step: u64,
+    pub address: usize,
+
+    //hash of the address and opcode of every instruction run so far, which differs once a replay has taken another path
+    pub path: u64,
+    pub flag: Flag,
+
+    //hashes of everything in the stack, registers and memory
+    pub stack: u64,
+    pub registers: u64,
+    pub memory: u64
+}
+
+impl Checkpoint {
+    fn new(step: u64, path: u64, state: &MachineState) -> Checkpoint {
+        let values = |values: &[u64]| values.iter().fold(FNV_OFFSET, |state, value| hash(state, &value.to_be_bytes()));
+        Checkpoint {
+            step,
+            address: state.address,
+            path,
+            flag: state.flag,
+            stack: values(state.stack),
+            registers: values(state.registers),
+            memory: values(state.memory)
+        }
+    }
+
+    //what about found isn't the same as this checkpoint, for explaining how a replay diverged
+    fn differences(&self, found: &Checkpoint) -> String {
+        let mut differences = Vec::new();
+        if self.address != found.address {
+            differences.push(format!("expected address {} but reached {}", self.address, found.address));
+        }
+        if self.flag != found.flag {
+            differences.push(format!("expected flag {:?} but found {:?}", self.flag, found.flag));
+        }
+
+        for (difference, expected, found) in [("the stack differs", self.stack, found.stack), ("the registers differ", self.registers, found.registers), ("memory differs", self.memory, found.memory)] {
+            if expected != found {
+                differences.push(difference.to_string());
+            }
+        }
+
+        if differences.is_empty() {
+            differences.push("a different path was taken to get there".to_string());
+        }
+
+        differences.join(", ")
+    }
+}
+
+//the parts of the vm that a checkpoint summarises, borrowed after an instruction has run
+pub(crate) struct MachineState<'a> {
+    pub(crate) address: usize,
+    pub(crate) stack: &'a [u64],
+    pub(crate) registers: &'a [u64],
+    pub(crate) memory: &'a [u64],
+    pub(crate) flag: Flag
+}
+
+//everything needed to run a program again exactly as it ran when it was recorded
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub program_hash: u64,
+    pub steps: u64,
+    pub inputs: Vec<RecordedInput>,
+    pub checkpoints: Vec<Checkpoint>
+}
+
+impl Recording {
+    /*
+    Encodes the recording after its header and version as big endian fields:
+        program hash (u64), steps (u64),
+        input count (u64), then step (u64), kind (u8) and value (u64) for each,
+        checkpoint count (u64), then step (u64), address (u64), path (u64), flag (u8), stack (u64), registers (u64) and memory (u64) for each
+    */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = RECORDING_HEADER.to_vec();
+        output.extend_from_slice(&RECORDING_VERSION.to_be_bytes());
+        output.extend_from_slice(&self.program_hash.to_be_bytes());
+        output.extend_from_slice(&self.steps.to_be_bytes());
+
+        output.extend_from_slice(&(self.inputs.len() as u64).to_be_bytes());
+        for input in &self.inputs {
+            output.extend_from_slice(&input.step.to_be_bytes());
+            output.push(input.kind.into());
+            output.extend_from_slice(&input.value.to_be_bytes());
+        }
+
+        output.extend_from_slice(&(self.checkpoints.len() as u64).to_be_bytes());
+        for checkpoint in &self.checkpoints {
+            output.extend_from_slice(&checkpoint.step.to_be_bytes());
+            output.extend_from_slice(&(checkpoint.address as u64).to_be_bytes());
+            output.extend_from_slice(&checkpoint.path.to_be_bytes());
+            output.push(checkpoint.flag.into());
+            for hash in [checkpoint.stack, checkpoint.registers, checkpoint.memory] {
+                output.extend_from_slice(&hash.to_be_bytes());
+            }
+        }
+
+        output
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Recording, VmError> {
+        let mut reader = ByteReader::new(data, "recording", VmError::InvalidRecording);
+        reader.expect_header(&RECORDING_HEADER, RECORDING_VERSION)?;
+
+        let program_hash = reader.take_u64()?;
+        let steps = reader.take_u64()?;
+
+        let mut inputs = Vec::new();
+        for _ in 0..reader.take_count(17)? {
+            let step = reader.take_u64()?;
+            let kind = reader.take_u8()?;
+            let kind = InputKind::try_from(kind).map_err(|k| VmError::InvalidRecording(format!("Unknown input kind {}", k)))?;
+            inputs.push(RecordedInput { step, kind, value: reader.take_u64()? });
+        }
+
+        let mut checkpoints = Vec::new();
+        for _ in 0..reader.take_count(49)? {
+            let (step, address, path) = (reader.take_u64()?, reader.take_u64()? as usize, reader.take_u64()?);
+            let flag = reader.take_u8()?;
+            let flag = Flag::try_from(flag).map_err(|f| VmError::InvalidRecording(format!("Unknown flag {}", f)))?;
+            checkpoints.push(Checkpoint { step, address, path, flag, stack: reader.take_u64()?, registers: reader.take_u64()?, memory: reader.take_u64()? });
+        }
+
+        if !reader.is_finished() {
+            return Err(VmError::InvalidRecording("Unexpected data at the end of the recording".into()))
+        }
+
+        Ok(Recording { program_hash, steps, inputs, checkpoints })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Record,
+    Replay
+}
+
+/*
+Either builds up a recording or checks a run against one.
+The whole state is checked after every taken branch and input read, so a replay is stopped with an error at the first place its path or state can differ.
+*/
+pub(crate) struct Session {
+    mode: Mode,
+    recording: Recording,
+    steps: u64,
+    path: u64,
+
+    //whether an input has been read since the last instruction, which the next checkpoint is taken after
+    input_read: bool,
+    next_input: usize,
+    next_checkpoint: usize
+}
+
+impl Session {
+    pub(crate) fn record() -> Session {
+        Session { mode: Mode::Record, recording: Recording::default(), steps: 0, path: FNV_OFFSET, input_read: false, next_input: 0, next_checkpoint: 0 }
+    }
+
+    pub(crate) fn replay(recording: Recording) -> Session {
+        Session { mode: Mode::Replay, recording, steps: 0, path: FNV_OFFSET, input_read: false, next_input: 0, next_checkpoint: 0 }
+    }
+
+    //taken is whether the instruction went somewhere other than the instruction after it
+    pub(crate) fn after_instruction(&mut self, program: &[u8], opcode: OpCode, taken: bool, state: MachineState) -> Result<(), VmError> {
+        if self.steps == 0 {
+            let program_hash = hash(FNV_OFFSET, program);
+            match self.mode {
+                Mode::Record => self.recording.program_hash = program_hash,
+                Mode::Replay if program_hash != self.recording.program_hash => return Err(VmError::ReplayDiverged("Replay diverged: the program being run is not the one that was recorded".into())),
+                Mode::Replay => ()
+            }
+        }
+
+        self.steps += 1;
+        self.path = hash(self.path, &(state.address as u64).to_be_bytes());
+        self.path = hash(self.path, &[opcode.into()]);
+
+        //a replay also stops where the recording has a checkpoint, so a branch it didn't take is still noticed
+        let expected = self.mode == Mode::Replay && self.recording.checkpoints.get(self.next_checkpoint).is_some_and(|checkpoint| checkpoint.step == self.steps);
+        if taken || self.input_read || expected || self.steps.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoint(&state)?
+        }
+        self.input_read = false;
+
+        Ok(())
+    }
+
+    fn checkpoint(&mut self, state: &MachineState) -> Result<(), VmError> {
+        let checkpoint = Checkpoint::new(self.steps, self.path, state);
+
+        match self.mode {
+            Mode::Record => self.recording.checkpoints.push(checkpoint),
+            Mode::Replay => {
+                match self.recording.checkpoints.get(self.next_checkpoint) {
+                    Some(expected) if *expected == checkpoint => self.next_checkpoint += 1,
+                    Some(expected) if expected.step == checkpoint.step => return Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: {}", checkpoint.step, expected.differences(&checkpoint)))),
+                    Some(_) => return Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: the instruction at address {} went somewhere the recording didn't", checkpoint.step, checkpoint.address))),
+                    None => return Err(VmError::ReplayDiverged(format!("Replay diverged: execution continued past step {}, where the recording ended", self.recording.steps)))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn input<F: FnOnce() -> u64>(&mut self, kind: InputKind, source: F) -> Result<u64, VmError> {
+        self.input_read = true;
+        match self.mode {
+            Mode::Record => {
+                let value = source();
+                self.recording.inputs.push(RecordedInput { step: self.steps, kind, value });
+                Ok(value)
+            },
+            Mode::Replay => {
+                match self.recording.inputs.get(self.next_input) {
+                    Some(input) if input.step == self.steps && input.kind == kind => {
+                        self.next_input += 1;
+                        Ok(input.value)
+                    },
+                    Some(input) => Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: {:?} input requested but the recording has {:?} input at step {}", self.steps, kind, input.kind, input.step))),
+                    None => Err(VmError::ReplayDiverged(format!("Replay diverged at step {}: {:?} input requested but the recording has no more inputs", self.steps, kind)))
+                }
+            }
+        }
+    }
+
+    //Ends the session, returning the recording or an error if a replay stopped before the recording did
+    pub(crate) fn finish(mut self, state: MachineState) -> Result<Recording, VmError> {
+        match self.mode {
+            Mode::Record => {
+                self.recording.steps = self.steps;
+                self.checkpoint(&state)?;
+                Ok(self.recording)
+            },
+            Mode::Replay => {
+                let last = Checkpoint::new(self.steps, self.path, &state);
+
+                if self.steps != self.recording.steps {
+                    return Err(VmError::ReplayDiverged(format!("Replay stopped after {} steps at address {}, but the recording stopped after {} steps", self.steps, state.address, self.recording.steps)))
+                }
+
+                if let Some(expected) = self.recording.checkpoints.last().filter(|expected| **expected != last) {
+                    return Err(VmError::ReplayDiverged(format!("Replay stopped after {} steps like the recording, but {}", self.steps, expected.differences(&last))))
+                }
+
+                if self.next_input != self.recording.inputs.len() {
+                    return Err(VmError::ReplayDiverged(format!("Replay used {} of the {} recorded inputs", self.next_input, self.recording.inputs.len())))
+                }
+
+                Ok(self.recording)
+            }
+        }
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+//FNV-1a, which is stable across hosts and versions unlike std's hasher
+fn hash(mut state: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        state ^= *byte as u64;
+        state = state.wrapping_mul(FNV_PRIME);
+    }
+    state
+}
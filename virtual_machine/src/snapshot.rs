@@ -0,0 +1,111 @@
+use std::convert::TryFrom;
+
+use crate::data::DataSection;
+use crate::error::VmError;
+use crate::flag::Flag;
+use crate::reader::ByteReader;
+use crate::vm::Machine;
+
+//Code at the start of all snapshots. The numbers decode to "Nariva Snapshot"
+pub const SNAPSHOT_HEADER: [u8; 15] = [78, 97, 114, 105, 118, 97, 32, 83, 110, 97, 112, 115, 104, 111, 116];
+
+/*
+Version of the layout written after the header. Every field is stored big endian in this order:
+    program length (u64), program bytes followed by its data section if it has one,
+    program address (u64),
+    stack length (u64), stack numbers (u64 each),
+    return address count (u64), return addresses (u64 each),
+    the 8 registers (u64 each),
+    flag (u8),
+    memory length in bytes (u64), memory numbers (u64 each).
+*/
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+impl Machine {
+    //Encodes the complete state of the vm so that it can be resumed later, possibly on another host
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut output = SNAPSHOT_HEADER.to_vec();
+        output.extend_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+
+        //the data section is kept on the end of the program, as it is in a binary
+        let program = [self.program.clone(), self.data.to_bytes()].concat();
+        output.extend_from_slice(&(program.len() as u64).to_be_bytes());
+        output.extend_from_slice(&program);
+
+        output.extend_from_slice(&(self.program_address as u64).to_be_bytes());
+
+        output.extend_from_slice(&(self.stack.len() as u64).to_be_bytes());
+        for num in &self.stack {
+            output.extend_from_slice(&num.to_be_bytes());
+        }
+
+        output.extend_from_slice(&(self.return_addresses.len() as u64).to_be_bytes());
+        for address in &self.return_addresses {
+            output.extend_from_slice(&(*address as u64).to_be_bytes());
+        }
+
+        for register in &self.registers {
+            output.extend_from_slice(&register.to_be_bytes());
+        }
+
+        output.push(self.flag.into());
+
+        output.extend_from_slice(&(self.memory.len() as u64 * 8).to_be_bytes());
+        for num in &self.memory {
+            output.extend_from_slice(&num.to_be_bytes());
+        }
+
+        output
+    }
+
+    /*
+    Replaces the state of the vm with one produced by snapshot().
+    Settings that are not part of the state, such as the output and whether instructions are shown, are kept.
+    */
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), VmError> {
+        let mut reader = ByteReader::new(snapshot, "snapshot", VmError::InvalidSnapshot);
+        reader.expect_header(&SNAPSHOT_HEADER, SNAPSHOT_VERSION)?;
+
+        let program_len = reader.take_count(1)?;
+        let mut program = reader.take(program_len)?.to_vec();
+        let (end, data) = DataSection::split(&program)?;
+        program.truncate(end);
+
+        let program_address = reader.take_u64()? as usize;
+
+        let stack_len = reader.take_count(8)?;
+        let stack = reader.take_nums(stack_len)?;
+
+        let return_len = reader.take_count(8)?;
+        let return_addresses = reader.take_nums(return_len)?.into_iter().map(|address| address as usize).collect();
+
+        let mut registers = [0; 8];
+        for register in registers.iter_mut() {
+            *register = reader.take_u64()?;
+        }
+
+        let flag = reader.take_u8()?;
+        let flag = Flag::try_from(flag).map_err(|f| VmError::InvalidSnapshot(format!("Unknown flag {}", f)))?;
+
+        let memory_len = reader.take_count(1)?;
+        if memory_len % 8 != 0 {
+            return Err(VmError::InvalidSnapshot(format!("Memory length {} is not a whole number of 8 byte numbers", memory_len)))
+        }
+        let memory = reader.take_nums(memory_len / 8)?;
+
+        if !reader.is_finished() {
+            return Err(VmError::InvalidSnapshot("Unexpected data at the end of the snapshot".into()))
+        }
+
+        self.program = program;
+        self.data = data;
+        self.program_address = program_address;
+        self.stack = stack;
+        self.return_addresses = return_addresses;
+        self.registers = registers;
+        self.memory = memory;
+        self.flag = flag;
+
+        Ok(())
+    }
+}
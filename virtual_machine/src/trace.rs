@@ -0,0 +1,183 @@
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use crate::error::VmError;
+use crate::flag::Flag;
+use crate::instruction::OpCode;
+use crate::reader::ByteReader;
+
+//Code at the start of all binary traces. The numbers decode to "Nariva Trace"
+pub const TRACE_HEADER: [u8; 12] = [78, 97, 114, 105, 118, 97, 32, 84, 114, 97, 99, 101];
+
+pub const TRACE_VERSION: u16 = 1;
+
+//record of a single executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    //position of the opcode in the program
+    pub address: usize,
+    pub opcode: OpCode,
+    pub operands: Vec<u64>,
+    pub stack_before: Vec<u64>,
+    pub stack_after: Vec<u64>,
+
+    //register index and the value written to it
+    pub registers_written: Vec<(usize, u64)>,
+
+    //flag after the instruction was executed
+    pub flag: Flag
+}
+
+//hook which is called by the vm after every instruction it executes
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+/*
+Writes each event as a single line of JSON, e.g.
+{"address":18,"opcode":"Push","operands":[20],"stack_before":[],"stack_after":[20],"registers_written":[],"flag":"None"}
+Writing stops at the first io error, which is returned by finish().
+*/
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> JsonLinesTracer<W> {
+        JsonLinesTracer { writer, error: None }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => {
+                self.writer.flush()?;
+                Ok(self.writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_some() {
+            return
+        }
+
+        let registers: Vec<String> = event.registers_written.iter().map(|(register, value)| format!("{{\"register\":{},\"value\":{}}}", register, value)).collect();
+
+        let line = format!("{{\"address\":{},\"opcode\":\"{:?}\",\"operands\":{},\"stack_before\":{},\"stack_after\":{},\"registers_written\":[{}],\"flag\":\"{:?}\"}}",
+            event.address, event.opcode, json_list(&event.operands), json_list(&event.stack_before), json_list(&event.stack_after), registers.join(","), event.flag);
+
+        if let Err(e) = writeln!(self.writer, "{}", line) {
+            self.error = Some(e)
+        }
+    }
+}
+
+fn json_list(nums: &[u64]) -> String {
+    let nums: Vec<String> = nums.iter().map(|n| n.to_string()).collect();
+    format!("[{}]", nums.join(","))
+}
+
+/*
+Writes events in a compact big endian format, after the trace header and version:
+    address (u64), opcode (u8),
+    operand count (u8), operands (u64 each),
+    stack length before (u64), stack before (u64 each),
+    stack length after (u64), stack after (u64 each),
+    registers written (u8), register index (u8) and value (u64) for each,
+    flag (u8)
+Writing stops at the first io error, which is returned by finish().
+*/
+pub struct BinaryTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>
+}
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(mut writer: W) -> BinaryTracer<W> {
+        let error = writer.write_all(&TRACE_HEADER).and_then(|_| writer.write_all(&TRACE_VERSION.to_be_bytes())).err();
+        BinaryTracer { writer, error }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => {
+                self.writer.flush()?;
+                Ok(self.writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_some() {
+            return
+        }
+
+        let mut record = Vec::new();
+        record.extend_from_slice(&(event.address as u64).to_be_bytes());
+        record.push(event.opcode.into());
+
+        record.push(event.operands.len() as u8);
+        for operand in &event.operands {
+            record.extend_from_slice(&operand.to_be_bytes());
+        }
+
+        for stack in [&event.stack_before, &event.stack_after].iter() {
+            record.extend_from_slice(&(stack.len() as u64).to_be_bytes());
+            for num in stack.iter() {
+                record.extend_from_slice(&num.to_be_bytes());
+            }
+        }
+
+        record.push(event.registers_written.len() as u8);
+        for (register, value) in &event.registers_written {
+            record.push(*register as u8);
+            record.extend_from_slice(&value.to_be_bytes());
+        }
+
+        record.push(event.flag.into());
+
+        if let Err(e) = self.writer.write_all(&record) {
+            self.error = Some(e)
+        }
+    }
+}
+
+//Decodes a trace written by a BinaryTracer
+pub fn read_binary_trace(data: &[u8]) -> Result<Vec<TraceEvent>, VmError> {
+    let mut reader = ByteReader::new(data, "trace", VmError::InvalidTrace);
+    reader.expect_header(&TRACE_HEADER, TRACE_VERSION)?;
+
+    let mut events = Vec::new();
+
+    while !reader.is_finished() {
+        let address = reader.take_u64()? as usize;
+        let opcode = OpCode::from(reader.take_u8()?);
+
+        let operand_count = reader.take_u8()?;
+        let operands = reader.take_nums(operand_count as usize)?;
+
+        let stack_len = reader.take_count(8)?;
+        let stack_before = reader.take_nums(stack_len)?;
+        let stack_len = reader.take_count(8)?;
+        let stack_after = reader.take_nums(stack_len)?;
+
+        let mut registers_written = Vec::new();
+        for _ in 0..reader.take_u8()? {
+            registers_written.push((reader.take_u8()? as usize, reader.take_u64()?));
+        }
+
+        let flag = reader.take_u8()?;
+        let flag = Flag::try_from(flag).map_err(|f| VmError::InvalidTrace(format!("Unknown flag {}", f)))?;
+
+        events.push(TraceEvent { address, opcode, operands, stack_before, stack_after, registers_written, flag })
+    }
+
+    Ok(events)
+}
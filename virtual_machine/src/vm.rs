@@ -1,12 +1,43 @@
-use std::process::Output;
+use std::io::{self, Write};
 
-use crate::instruction::{OpCode, ByteOps};
+use crate::instruction::OpCode;
 use crate::flag::Flag;
+use crate::trace::{Tracer, TraceEvent};
+use crate::profile::Profiler;
+use crate::coverage::Coverage;
+use crate::replay::{InputKind, MachineState, Recording, Session};
+use crate::error::{ASSERT_CODE, VmError};
+use crate::data::DataSection;
+
+//Most numbers memory can hold, so a STOREM to a huge address stops the program instead of allocating it all
+pub const MAX_MEMORY: usize = 1 << 20;
+
+//why a program stops on a RETURN that isn't inside a function
+pub(crate) const NO_RETURN: &str = "RETURN with no function to return to";
 
 //Code at the start of all programs to ensure that they are nariva files.
 //The numebrs decode to "Nariva Executable"
 pub const HEADER: [u8; 17] = [78, 97, 114, 105, 118, 97, 32, 69, 120, 101, 99, 117, 116, 97, 98, 108, 101];
 
+//what the vm does after applying an opcode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Flow {
+    Next,
+    Halt,
+    Jump,
+    Call,
+    Return,
+
+    //stops the program with an error, from ASSERT or TRAP
+    Trap(u64),
+
+    //stops the program as STOREM wrote to a memory address past MAX_MEMORY
+    OutOfMemory(u64),
+
+    //stops the program as it did something the vm can't, such as popping from an empty stack
+    Fault(&'static str),
+}
+
 pub struct Machine {
     //list of encoded instructions
     pub program: Vec<u8>,
@@ -23,11 +54,41 @@ pub struct Machine {
     //specific spaces to store data
     pub registers: [u64; 8],
 
+    //numbers stored by address, which grows as higher addresses are written to
+    pub memory: Vec<u64>,
+
+    //messages for ASSERT and TRAP instructions, split off the end of the program when it is loaded
+    pub data: DataSection,
+
+    //error the program stopped with, if it stopped on ASSERT, TRAP, by running out of memory or by a replay diverging
+    trap: Option<VmError>,
+
     //whether or not to print out instructions being executed
     show: bool,
 
     //result of comparison
-    flag: Flag
+    pub(crate) flag: Flag,
+
+    //where printed values are written to
+    output: Box<dyn Write>,
+
+    //receives a record of every executed instruction
+    tracer: Option<Box<dyn Tracer>>,
+
+    //counts executed instructions while profiling
+    profiler: Option<Profiler>,
+
+    //records which instructions and branches have run
+    coverage: Option<Coverage>,
+
+    //records or replays nondeterministic inputs
+    session: Option<Session>
+}
+
+impl Default for Machine {
+    fn default() -> Machine {
+        Machine::new()
+    }
 }
 
 impl Machine {
@@ -38,11 +99,105 @@ impl Machine {
             stack: Vec::new(),
             return_addresses: Vec::new(),
             registers: [0,0,0,0,0,0,0,0],
+            memory: Vec::new(),
+            data: DataSection::new(),
+            trap: None,
             show: false,
-            flag: Flag::None
+            flag: Flag::None,
+            output: Box::new(io::stdout()),
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            session: None
+        }
+    }
+
+    //Redirects everything the program prints, e.g. to a file or a SharedBuffer
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output
+    }
+
+    //Records every instruction executed from now on, until the tracer is taken back
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer)
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    //Counts instructions executed from now on, per address, opcode and function
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::new())
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    //Records which addresses are executed and which way conditional jumps go from now on
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new())
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    //Logs every nondeterministic input from now on, along with checkpoints that a replay must reach
+    pub fn start_recording(&mut self) {
+        self.session = Some(Session::record())
+    }
+
+    //Returns what has been logged since start_recording, or None if the vm was not recording
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        match self.session.take() {
+            Some(session) => session.finish(self.machine_state(self.program_address)).ok(),
+            None => None
+        }
+    }
+
+    //Feeds recorded inputs back to the vm from now on, stopping the program with a ReplayDiverged error as soon as execution differs from the recording
+    pub fn start_replay(&mut self, recording: Recording) {
+        self.session = Some(Session::replay(recording))
+    }
+
+    //Checks that the replay ended in the same place as the recording and used all of its inputs
+    pub fn finish_replay(&mut self) -> Result<(), VmError> {
+        match self.session.take() {
+            Some(session) => session.finish(self.machine_state(self.program_address)).map(|_| ()),
+            None => Err(VmError::ReplayDiverged("The vm was not replaying a recording".into()))
+        }
+    }
+
+    /*
+    Every value which can differ between runs, such as user input, host function results, the time or random numbers, must be read through here.
+    The source is only called when not replaying, otherwise the recorded value is returned instead, or an error if the recording has no such input.
+    */
+    pub fn nondeterministic_input<F: FnOnce() -> u64>(&mut self, kind: InputKind, source: F) -> Result<u64, VmError> {
+        match self.session.as_mut() {
+            Some(session) => session.input(kind, source),
+            None => Ok(source())
         }
     }
 
+    pub fn flag(&self) -> Flag {
+        self.flag
+    }
+
+    //what a replay checkpoint is taken of, with the instruction at address having just run
+    fn machine_state(&self, address: usize) -> MachineState<'_> {
+        MachineState { address, stack: &self.stack, registers: &self.registers, memory: &self.memory, flag: self.flag }
+    }
+
     pub fn next_8_bits(&mut self) -> u8 {
         self.program_address += 1;
         self.program[self.program_address]
@@ -57,36 +212,86 @@ impl Machine {
     }
 
 
-    //Loop that runs until program ends or HALT upcode is reached
+    //Loads a program and runs it from the start
     pub fn run(&mut self, program: Vec<u8>, show: bool) -> u64 {
-        self.program = program;
         self.show = show;
 
         //checks to make sure file header is correct
-        if self.is_nariva_file() {
-            //sets th eprogramm address to the end of the header
-            self.program_address = HEADER.len() - 1;
-    
-            loop {
-                self.execute_instruction();
-
-                /*if at the end of the program, 
-                if the stack is empty return the largest 64but unsigned interger, 
-                else return the last num on the stack
-                */
-                if self.program_address >= self.program.len() - 1 {
-                    return match self.stack.pop() {
-                        Some(num) => num,
-                        None => u64::MAX
-                    }
-                }
-            }
+        if self.load(program) {
+            self.resume()
         }
         else {
             u64::MAX
         }
     }
 
+    //Sets the program address to the end of the header without executing anything, returning whether the program is a nariva file with a readable data section
+    pub fn load(&mut self, mut program: Vec<u8>) -> bool {
+        let (end, data) = match DataSection::split(&program) {
+            Ok(split) => split,
+            Err(_) => return false
+        };
+        program.truncate(end);
+
+        self.program = program;
+        self.data = data;
+        self.trap = None;
+        self.program_address = HEADER.len() - 1;
+        self.is_nariva_file()
+    }
+
+    /*
+    Loop that runs from the current program address until program ends or HALT upcode is reached.
+    if the stack is empty return the largest 64but unsigned interger, 
+    else return the last num on the stack
+    */
+    pub fn resume(&mut self) -> u64 {
+        while !self.is_finished() {
+            self.execute_instruction();
+        }
+
+        self.stack.pop().unwrap_or(u64::MAX)
+    }
+
+    //Same as resume, but stops with an error once fuel instructions have run, so programs that never finish can't run forever
+    pub fn resume_with_fuel(&mut self, fuel: u64) -> Result<u64, VmError> {
+        let mut used = 0;
+        while !self.is_finished() {
+            if used == fuel {
+                return Err(VmError::OutOfFuel(format!("Program ran out of fuel after {} instructions, at address {}", used, self.program_address + 1)))
+            }
+
+            self.execute_instruction();
+            used += 1;
+        }
+
+        if let Some(trap) = self.trap.take() {
+            return Err(trap)
+        }
+
+        Ok(self.stack.pop().unwrap_or(u64::MAX))
+    }
+
+    //error the program stopped with, if it stopped on ASSERT, TRAP, by running out of memory or by a replay diverging
+    pub fn trap(&self) -> Option<&VmError> {
+        self.trap.as_ref()
+    }
+
+    //records why the program stopped on the instruction at address
+    pub(crate) fn stop(&mut self, flow: Flow, address: usize) {
+        self.trap = match flow {
+            Flow::Trap(code) => Some(VmError::Trap { code, address, message: self.data.message(address).map(String::from) }),
+            Flow::OutOfMemory(target) => Some(VmError::OutOfMemory(format!("STOREM at address {} writes to memory address {}, but memory only holds {} numbers", address, target, MAX_MEMORY))),
+            Flow::Fault(reason) => Some(VmError::Fault(format!("{} at address {}", reason, address))),
+            _ => None
+        };
+    }
+
+    //whether the last instruction in the program has been executed
+    pub fn is_finished(&self) -> bool {
+        self.program_address + 1 >= self.program.len()
+    }
+
     pub fn execute_instruction(&mut self) {
         let opcode = self.next_8_bits().into();
 
@@ -94,24 +299,170 @@ impl Machine {
             println!("{:?}, {}, {}, {:?}, {:?}, {:?}", opcode, self.program_address, self.program_address - HEADER.len(), self.stack, self.registers, self.flag);
         }
 
-        match opcode {
-            //Ends program if a HALT or ILLEGAL upcode is found
-            OpCode::Illegal => {
-                println!("\nIllegal / unknown upcode reached at {}, current stack: {:?}", self.program_address, self.stack);
-                println!("Program: {:?}\nReturn Address: {:?}", self.program, self.return_addresses);
-                println!("Flag: {:?}", self.flag);
-                panic!()
+        let address = self.program_address;
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(self.program_address, opcode)
+        }
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(self.program_address, self.flag.jump_taken(opcode))
+        }
+
+        if self.tracer.is_none() {
+            self.execute_opcode(opcode)
+        }
+        else {
+            self.execute_traced(opcode)
+        }
+
+        //functions are entered and left at CALL and RETURN boundaries
+        if let Some(profiler) = self.profiler.as_mut() {
+            match opcode {
+                OpCode::Call => profiler.enter(self.program_address + 1),
+                OpCode::Return => profiler.exit(),
+                _ => ()
+            }
+        }
+
+        //a replay that goes its own way stops the program, with the error given by resume_with_fuel and trap()
+        if let Some(mut session) = self.session.take() {
+            let taken = self.program_address != address + 8 * opcode.operand_count();
+            if let Err(diverged) = session.after_instruction(&self.program, opcode, taken, self.machine_state(address)) {
+                self.trap = Some(diverged);
+                self.program_address = self.program.len();
+            }
+            self.session = Some(session);
+        }
+    }
+
+    //state is only copied while tracing so untraced runs don't pay for it
+    fn execute_traced(&mut self, opcode: OpCode) {
+        let address = self.program_address;
+        let operands: Vec<u64> = (0..opcode.operand_count()).filter_map(|i| {
+            let start = address + 1 + i * 8;
+            self.program.get(start..start + 8).map(|bytes| u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]))
+        }).collect();
+        let stack_before = self.stack.clone();
+
+        self.execute_opcode(opcode);
+
+        //STORE is the only opcode which writes to a register
+        let registers_written = match (opcode, operands.first()) {
+            (OpCode::Store, Some(register)) => self.registers.get(*register as usize).map(|value| vec![(*register as usize, *value)]).unwrap_or_default(),
+            _ => Vec::new()
+        };
+
+        let event = TraceEvent {
+            address,
+            opcode,
+            operands,
+            stack_before,
+            stack_after: self.stack.clone(),
+            registers_written,
+            flag: self.flag
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&event)
+        }
+    }
+
+    fn execute_opcode(&mut self, opcode: OpCode) {
+        //operands are read before the opcode is applied, leaving the address at the end of the instruction
+        let address = self.program_address;
+        let operand = if opcode.operand_count() == 1 { self.next_64_bits() } else { 0 };
+
+        let flow = self.apply(opcode, operand);
+        match flow {
+            Flow::Next => (),
+
+            Flow::Halt => self.program_address = self.program.len(),
+
+            Flow::Trap(_) | Flow::OutOfMemory(_) | Flow::Fault(_) => {
+                self.stop(flow, address);
+                self.program_address = self.program.len()
             },
 
-            OpCode::Halt => self.program_address = self.program.len(),
+            //Minus 9 bits to get back to the jump opcode, as jump distances are measured from there and the next iteration goes to the opcode after the given address
+            Flow::Jump => {
+                let int_jump_dist = i64::from_be_bytes(operand.to_be_bytes());
+                self.program_address -= 9;
+
+                if int_jump_dist > 0 {
+                    self.program_address += int_jump_dist.unsigned_abs() as usize
+                }
+                else {
+                    self.program_address -= int_jump_dist.unsigned_abs() as usize
+                }
+            },
+
+            Flow::Call => {
+                self.return_addresses.push(self.program_address);
+                self.program_address = operand as usize;
+            },
+
+            Flow::Return => match self.return_addresses.pop() {
+                Some(return_address) => self.program_address = return_address,
+                None => {
+                    self.stop(Flow::Fault(NO_RETURN), address);
+                    self.program_address = self.program.len()
+                }
+            }
+        }
+    }
+
+    //what stops an opcode from being applied, checked first so a broken program stops with an error rather than a panic
+    fn fault(&self, opcode: OpCode, operand: u64) -> Option<&'static str> {
+        let popped = match opcode {
+            OpCode::AddU | OpCode::SubU | OpCode::MulU | OpCode::DivU | OpCode::AddI | OpCode::SubI | OpCode::MulI | OpCode::DivI |
+            OpCode::AddF | OpCode::SubF | OpCode::MulF | OpCode::DivF | OpCode::Shift | OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor |
+            OpCode::CMP | OpCode::ModU | OpCode::ModI | OpCode::ModF => 2,
+            OpCode::BitNot | OpCode::Print | OpCode::Dupli | OpCode::Store | OpCode::StoreM |
+            OpCode::UtoF | OpCode::ItoF | OpCode::FtoU | OpCode::FtoI => 1,
+            OpCode::PrintSTR => operand,
+            _ => 0
+        };
+
+        match opcode {
+            OpCode::Illegal => Some("Illegal opcode"),
+            _ if (self.stack.len() as u64) < popped => Some("Not enough numbers on the stack"),
+            OpCode::ModU | OpCode::ModI if self.stack.last() == Some(&0) => Some("Division by 0"),
+            OpCode::Shift if self.stack.last().is_some_and(|bits| *bits >= 64) => Some("Shift by 64 bits or more"),
+            OpCode::Store | OpCode::Load if operand >= self.registers.len() as u64 => Some("Register that doesn't exist"),
+            OpCode::Print if operand > 3 => Some("Unknown PRINT format"),
+            OpCode::CMP if operand > 2 => Some("Unknown CMP format"),
+            _ => None
+        }
+    }
+
+    /*
+    Performs everything an opcode does to the stack, registers, flag and output.
+    Changes to the program address are left to the caller, so that the same semantics are shared by the byte interpreter and decoded programs.
+    */
+    pub(crate) fn apply(&mut self, opcode: OpCode, operand: u64) -> Flow {
+        if let Some(reason) = self.fault(opcode, operand) {
+            return Flow::Fault(reason)
+        }
+
+        match opcode {
+            //ILLEGAL opcodes are stopped by fault
+            OpCode::Illegal => (),
+
+            OpCode::Halt => return Flow::Halt,
+
+            //an empty stack fails the assertion too, rather than stopping the vm with a panic
+            OpCode::Assert => if matches!(self.stack.pop(), Some(0) | None) {
+                return Flow::Trap(ASSERT_CODE)
+            },
+            OpCode::Trap => return Flow::Trap(operand),
 
             /*
             Appends a number to the stack. 
             This number either has 8, 16, 32, or 64 bits depending on what is specified by the next 8 bits following the opcode
             */
             OpCode::Push => {
-                let new_num = self.next_64_bits();
-                self.stack.push(new_num)
+                self.stack.push(operand)
             },
 
             //Removes a number from the stack
@@ -238,7 +589,7 @@ impl Machine {
             //Shifts th ebits in the number to the left or right depenidng on the number that immediateley follows the opcode
             OpCode::Shift => {
                 let [num1, num2] = self.double_pop();
-                if self.next_64_bits() == 0 {
+                if operand == 0 {
                     self.stack.push(num2 << num1)
                 }
                 else {
@@ -268,7 +619,7 @@ impl Machine {
             },
 
             OpCode::CMP => {
-                match self.next_64_bits() {
+                match operand {
                     0 => {
                         let [num1, num2] = self.double_pop();
                         //println!("{:?}", [num1, num2]);
@@ -311,53 +662,18 @@ impl Machine {
                 }
             },
 
-            OpCode::JMP => self.jump(),
+            OpCode::JMP => return Flow::Jump,
 
-
-            OpCode::JE => {
-                match self.flag {
-                    Flag::Equal => {
-                        self.jump()
-                    },
-                    _ => self.program_address += 8
-                } 
-            },
-
-            OpCode::JNE => {
-                match self.flag {
-                    Flag::Greater | Flag::Less => {
-                        self.jump()
-                    },
-                    _ => self.program_address += 8
-                } 
-            },
-
-            OpCode::JG => {
-                match self.flag {
-                    Flag::Greater => {
-                        self.jump()
-                    },
-                    _ => self.program_address += 8
-                } 
+            //jump if equal, not equal, greater, less
+            OpCode::JE | OpCode::JNE | OpCode::JG | OpCode::JL => {
+                if self.flag.jump_taken(opcode) == Some(true) {
+                    return Flow::Jump
+                }
             },
 
-            OpCode::JL => {
-                match self.flag {
-                    Flag::Less => {
-                        self.jump()
-                    },
-                    _ => self.program_address += 8
-                } 
-            },
+            OpCode::Call => return Flow::Call,
 
-            OpCode::Call => {
-                self.return_addresses.push(self.program_address + 8);
-                self.program_address = self.next_64_bits() as usize;
-            },
-
-            OpCode::Return => {
-                self.program_address = self.return_addresses.pop().unwrap()
-            },
+            OpCode::Return => return Flow::Return,
 
             OpCode::ModU => {
                 let [num1, num2] = self.double_pop();
@@ -367,7 +683,14 @@ impl Machine {
             OpCode::ModI => {
                 let [num1, num2] = self.double_pop();
                 let [num1, num2] = [i64::from_be_bytes(num1.to_be_bytes()), i64::from_be_bytes(num2.to_be_bytes())];
-                self.stack.push(u64::from_be_bytes((num2 % num1).to_be_bytes()))
+                match num2.checked_rem(num1) {
+                    Some(result) => self.stack.push(u64::from_be_bytes(result.to_be_bytes())),
+                    None => {
+                        self.stack.push(0);
+                        self.flag = Flag::Overflow;
+                    }
+
+                };
             },
 
             OpCode::ModF => {
@@ -377,11 +700,11 @@ impl Machine {
             },
 
             OpCode::Print => {
-                match self.next_64_bits() {
-                    0 => println!("{}", self.stack.pop().unwrap() as u64),
-                    1 => println!("{}", self.stack.pop().unwrap() as i64),
-                    2 => println!("{}", self.stack.pop().unwrap() as f64),
-                    3 => println!("{}", self.stack.pop().unwrap() as u8 as char),
+                match operand {
+                    0 => writeln!(self.output, "{}", self.stack.pop().unwrap()).unwrap(),
+                    1 => writeln!(self.output, "{}", self.stack.pop().unwrap() as i64).unwrap(),
+                    2 => writeln!(self.output, "{}", f64::from_be_bytes(self.stack.pop().unwrap().to_be_bytes())).unwrap(),
+                    3 => writeln!(self.output, "{}", self.stack.pop().unwrap() as u8 as char).unwrap(),
                     _ => unimplemented!()
 
                 }
@@ -394,38 +717,67 @@ impl Machine {
             },
 
             OpCode::Store => {
-                let register_index = self.next_64_bits() as usize;
+                let register_index = operand as usize;
                 self.registers[register_index] = self.stack.pop().unwrap()
             }
 
             OpCode::Load => {
-                let register_index = self.next_64_bits() as usize;
+                let register_index = operand as usize;
                 self.stack.push(self.registers[register_index]);
             }
 
+            //addresses that have never been written to hold 0, and memory never grows past MAX_MEMORY
+            OpCode::StoreM => {
+                if operand >= MAX_MEMORY as u64 {
+                    return Flow::OutOfMemory(operand)
+                }
+
+                let address = operand as usize;
+                if address >= self.memory.len() {
+                    self.memory.resize(address + 1, 0);
+                }
+                self.memory[address] = self.stack.pop().unwrap()
+            }
+
+            OpCode::LoadM => {
+                let address = operand as usize;
+                self.stack.push(self.memory.get(address).copied().unwrap_or(0));
+            }
+
             OpCode::PrintSTR => {
-                let output_len = self.next_64_bits() as usize;
+                let output_len = operand as usize;
                 let output: Vec<u8> = self.stack.drain((self.stack.len() - output_len)..).map(|c| c as u8).collect();
                 let output = String::from_utf8_lossy(&output);
-                println!("{}", output)
+                writeln!(self.output, "{}", output).unwrap()
             }
-        }
-    }
-
-    fn jump(&mut self) {
-        let jump_bits = self.next_64_bits();
-        let int_jump_dist = i64::from_be_bytes(jump_bits.to_be_bytes());
 
-        //Minus 8 bits because next_64_bits() already increases address by 8 and next iteration goes to opcode after given address
-        self.program_address -= 9;
+            OpCode::UtoF => {
+                let num = self.stack.pop().unwrap() as f64;
+                self.stack.push(u64::from_be_bytes(num.to_be_bytes()))
+            }
+            OpCode::ItoF => {
+                let num = i64::from_be_bytes(self.stack.pop().unwrap().to_be_bytes()) as f64;
+                self.stack.push(u64::from_be_bytes(num.to_be_bytes()))
+            }
 
-        if int_jump_dist > 0 {
-            self.program_address += int_jump_dist.abs() as usize
-        }
-        else {
-            self.program_address -= int_jump_dist.abs() as usize
+            //floats which are out of range, or not a number, are saturated and set the overflow flag
+            OpCode::FtoU => {
+                let num = f64::from_be_bytes(self.stack.pop().unwrap().to_be_bytes());
+                if num.is_nan() || num < 0.0 || num >= u64::MAX as f64 {
+                    self.flag = Flag::Overflow;
+                }
+                self.stack.push(num as u64)
+            }
+            OpCode::FtoI => {
+                let num = f64::from_be_bytes(self.stack.pop().unwrap().to_be_bytes());
+                if num.is_nan() || num < i64::MIN as f64 || num >= i64::MAX as f64 {
+                    self.flag = Flag::Overflow;
+                }
+                self.stack.push(u64::from_be_bytes((num as i64).to_be_bytes()))
+            }
         }
 
+        Flow::Next
     }
 
     //Removes and returns the last 2 numbers form the stack
@@ -443,16 +795,6 @@ impl Machine {
     }
 
     pub fn is_nariva_file(&self) -> bool {
-        if self.program.len() <= HEADER.len() {
-            return false
-        }
-        else {
-            if self.program[0..HEADER.len()] == HEADER {
-                return true
-            }
-            else {
-                return false
-            }
-        }
+        self.program.len() > HEADER.len() && self.program[0..HEADER.len()] == HEADER
     }
 }
\ No newline at end of file